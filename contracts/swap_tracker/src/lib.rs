@@ -1,9 +1,76 @@
 #![no_std]
 
 use soroban_sdk::{
-    contract, contractimpl, contracttype, symbol_short, vec, Address, Env, String, Vec,
+    contract, contracterror, contractimpl, contracttype, panic_with_error, symbol_short, vec,
+    xdr::ToXdr, Address, Bytes, BytesN, Env, IntoVal, Map, String, Symbol, TryFromVal, Val, Vec,
 };
 
+/// Errors returned by the contract.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    Overflow = 3,
+    Paused = 4,
+    CategoryNotAllowed = 5,
+    DuplicateTooSoon = 6,
+    SwapNotFound = 7,
+    NotPending = 8,
+    AmountTooSmall = 9,
+    AddressBlocked = 10,
+    BatchTooLarge = 11,
+    InvalidFee = 12,
+    InvalidParentId = 13,
+    NoAdmin = 14,
+    DuplicateSwap = 15,
+    RateLimited = 16,
+    InvalidRateScale = 17,
+    LedgerThrottled = 18,
+    ImportDisabled = 19,
+    IndexTaken = 20,
+    AppNotAllowed = 21,
+    InvalidTtl = 22,
+    PairNotApproved = 23,
+    Frozen = 24,
+    InvalidShardCount = 25,
+    InvalidRange = 26,
+    InvalidDecimals = 27,
+    InvalidPrice = 28,
+    TimestampCollision = 29,
+    InvalidSupply = 30,
+    MaintenanceDisabled = 31,
+    AmountTooLarge = 32,
+}
+
+/// Settlement status of a recorded swap.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SwapStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+/// Direction of a pair's rate relative to its previous swap.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TickDirection {
+    Up,
+    Down,
+    Flat,
+}
+
+/// How integer divisions deriving rates are rounded.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoundingMode {
+    Floor,
+    Ceil,
+    Nearest,
+}
+
 /// Represents a single swap record stored on-chain.
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -12,14 +79,322 @@ pub struct SwapRecord {
     pub from_asset: String,
     pub to_asset: String,
     pub amount: i128,
+    pub to_amount: i128,
+    pub timestamp: u64,
+    pub category: Symbol,
+    pub status: SwapStatus,
+    pub confirmations: u32,
+    pub swap_id: BytesN<32>,
+    pub ledger_seq: u32,
+    /// Parent order id for partial fills, always 32 bytes long. Held as
+    /// `Bytes` because soroban-sdk 21 has no infallible `ScVal` conversion
+    /// for `BytesN`, which `Option` fields of a `contracttype` require.
+    pub parent_id: Option<Bytes>,
+    pub app_id: Symbol,
+    /// Ledger timestamp after which a still-pending swap may be expired
+    /// to `Failed` by `expire_stale_swaps`. `0` means no deadline.
+    pub deadline_ts: u64,
+    /// Value of `amount` in the common quote currency at record time,
+    /// from the configured price of `from_asset`. `0` if it had no price.
+    pub quote_value: i128,
+    /// Fee accrued to `from_asset` at the fee rate in force when the swap
+    /// was recorded.
+    pub fee: i128,
+    /// Hash of the previously recorded swap, linking records into a
+    /// tamper-evident chain checked by `verify_chain`. All zeroes for the
+    /// first record.
+    pub prev_hash: BytesN<32>,
+}
+
+/// Storage form of a `SwapRecord`. Asset codes are replaced by their ids
+/// in the append-only `DataKey::AssetCodes` registry, which `read_swap`
+/// resolves back to strings.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct StoredSwap {
+    user: Address,
+    from_asset: u32,
+    to_asset: u32,
+    amount: i128,
+    to_amount: i128,
+    timestamp: u64,
+    category: Symbol,
+    status: SwapStatus,
+    confirmations: u32,
+    swap_id: BytesN<32>,
+    ledger_seq: u32,
+    parent_id: Option<Bytes>,
+    app_id: Symbol,
+    deadline_ts: u64,
+    quote_value: i128,
+    fee: i128,
+    prev_hash: BytesN<32>,
+}
+
+/// Swap record layout of deployments that predate schema versioning (version
+/// `1`). `read_swap` still decodes it until `migrate_schema` rewrites it.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct LegacySwap {
+    user: Address,
+    from_asset: String,
+    to_asset: String,
+    amount: i128,
+    timestamp: u64,
+}
+
+/// Schema version of event payloads, published as the second topic of
+/// every event. Bumped whenever a payload layout changes.
+const EVENT_VERSION: u32 = 2;
+
+/// Default fixed-point scale applied to exchange rates.
+const DEFAULT_RATE_SCALE: i128 = 10_000_000;
+
+/// Number of seconds in a day bucket.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Number of seconds in an hour bucket.
+const SECONDS_PER_HOUR: u64 = 3_600;
+
+/// Maximum number of days returned by a daily series query.
+const MAX_SERIES_DAYS: u64 = 366;
+
+/// Number of power-of-ten buckets in the amount histogram, enough to
+/// cover every positive `i128`.
+const AMOUNT_BUCKETS: u32 = 39;
+
+/// Maximum number of indices `export_range` will read in one call.
+const MAX_EXPORT_RANGE: u64 = 100;
+
+/// Size in bytes of the duplicate pre-filter bit array.
+const DUP_FILTER_BYTES: u32 = 256;
+
+/// Number of bits set in the duplicate pre-filter per key.
+const DUP_FILTER_HASHES: u32 = 3;
+
+/// Default remaining TTL, in ledgers, below which a record is bumped
+/// (about one day).
+const DEFAULT_TTL_THRESHOLD: u32 = 17_280;
+
+/// Default TTL, in ledgers, a bumped record is extended to (about 30 days).
+const DEFAULT_TTL_EXTEND_TO: u32 = 518_400;
+
+/// Default maximum number of swaps accepted by `record_swaps_batch`.
+const DEFAULT_MAX_BATCH_SIZE: u32 = 50;
+
+/// Maximum number of records rolled off by a single swap when the record
+/// cap has been lowered below the number of live records.
+const MAX_DROPS_PER_SWAP: u64 = 10;
+
+/// Address shown in place of the user on records of users who opted out
+/// of appearing in reads (the all-zero account key).
+const PRIVATE_PLACEHOLDER: &str = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+/// Number of records beyond the requested count that
+/// `get_swaps_time_sorted` examines to catch out-of-order timestamps.
+const TIME_SORT_SLACK: u64 = 50;
+
+/// Decimals assumed for assets without configured metadata (Stellar's
+/// native precision).
+const DEFAULT_ASSET_DECIMALS: u32 = 7;
+
+/// Largest supported asset precision.
+const MAX_ASSET_DECIMALS: u32 = 18;
+
+/// Largest amount accepted for a single swap: a trillion whole units at 18
+/// decimals. Keeps one swap from pushing the running totals to the `i128`
+/// limit, which would fail every later swap with `Error::Overflow`.
+const MAX_SWAP_AMOUNT: i128 = 1_000_000_000_000_000_000_000_000_000_000;
+
+/// Current layout of stored swap records. Version `1` stored each
+/// `SwapRecord` as is; version `2` stores a `StoredSwap` with asset ids.
+const SCHEMA_VERSION: u32 = 2;
+
+/// Category and app id given to records decoded from the legacy layout,
+/// which carried neither.
+const LEGACY_TAG: Symbol = symbol_short!("legacy");
+
+/// Caller-supplied fields of a swap, as accepted by `record_swaps_batch`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SwapInput {
+    pub user: Address,
+    pub from_asset: String,
+    pub to_asset: String,
+    pub amount: i128,
+    pub to_amount: i128,
     pub timestamp: u64,
+    pub category: Symbol,
+    /// Parent order id for partial fills; see `SwapRecord::parent_id`.
+    /// Ids that aren't 32 bytes long are rejected with `InvalidParentId`.
+    pub parent_id: Option<Bytes>,
+    pub app_id: Symbol,
+    /// Ledger timestamp after which a still-pending swap may be expired
+    /// to `Failed` by `expire_stale_swaps`. `0` means no deadline.
+    pub deadline_ts: u64,
+}
+
+/// Per-user aggregate statistics.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UserStats {
+    pub count: u64,
+    pub volume: i128,
+    pub last_timestamp: u64,
+}
+
+/// Per-asset aggregate row returned by `get_asset_summaries`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AssetSummary {
+    pub asset: String,
+    pub volume: i128,
+    pub count: u64,
+    pub last_rate: i128,
+}
+
+/// Contract-wide overview returned by `get_summary`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Summary {
+    pub total_swaps: u64,
+    pub last_timestamp: u64,
+    pub distinct_assets: u32,
+    pub paused: bool,
+}
+
+/// Per-pair aggregate row returned by `get_pair_stats`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PairStats {
+    pub from_asset: String,
+    pub to_asset: String,
+    pub volume: i128,
+    pub count: u64,
+    pub last_rate: i128,
+}
+
+/// A page of swap records returned by `get_cursor_page`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CursorPage {
+    pub records: Vec<SwapRecord>,
+    pub next_cursor: Option<u64>,
+    pub has_more: bool,
+}
+
+/// Instance storage keys for the admin and contract-wide configuration.
+#[contracttype]
+pub enum ConfigKey {
+    Admin,
+    AggregationEnabled,
+    Paused,
+    CircuitMultiplier,
+    DuplicateWindow,
+    ConfirmationThreshold,
+    MinAmount,
+    MilestoneInterval,
+    MaxBatchSize,
+    FeeBps,
+    AdminRenounced,
+    RoundingMode,
+    IdempotencyTtl,
+    MinSwapInterval,
+    RateScale,
+    MaxSwapsPerLedger,
+    ImportMode,
+    UserMilestone,
+    TtlThreshold,
+    TtlExtendTo,
+    PairAllowlistEnabled,
+    Frozen,
+    EventShards,
+    HookContract,
+    SchemaVersion,
+    MaxRecords,
+    RequireMonotonicTs,
+    ChainHead,
+    MaintenanceMode,
+    RunningVolume,
+}
+
+/// Persistent storage keys for the pair registry and per-`(from, to)` pair
+/// data.
+#[contracttype]
+pub enum PairKey {
+    List,
+    Approved(String, String),
+    LastRate(String, String),
+    Tick(String, String),
+    RateMin(String, String),
+    RateMax(String, String),
+    Candle(String, String, u64),
+    Volume(String, String),
+    Count(String, String),
 }
 
-/// Storage keys used by the contract.
+/// Persistent storage keys for per-day aggregates, keyed by day index
+/// (`timestamp / SECONDS_PER_DAY`). `HourVolume` is keyed by hour index
+/// (`timestamp / SECONDS_PER_HOUR`) instead.
+#[contracttype]
+pub enum DayKey {
+    Count(u64),
+    Volume(u64),
+    HourVolume(u64),
+    Active,
+    ActiveCount,
+    Busiest,
+}
+
+/// Storage keys for swap records, indices and aggregates.
 #[contracttype]
 pub enum DataKey {
     SwapCount,
     Swap(u64),
+    UserSwapCount(Address),
+    UserVolume(Address),
+    UserLastTs(Address),
+    UserSwaps(Address),
+    TotalVolume,
+    MaxAmount,
+    AllowedCategory(Symbol),
+    CategoryCount(Symbol),
+    UserLastSwap(Address),
+    UserAssetVolume(Address, String),
+    SwapById(BytesN<32>),
+    AssetMin(String),
+    SwapFlag(u64),
+    FlaggedSwaps,
+    AssetOutCount(String),
+    AssetInCount(String),
+    Blocked(Address),
+    AmountHistogram,
+    AssetList,
+    AssetFees(String),
+    AssetPrice(String),
+    ParentSwaps(BytesN<32>),
+    AssetVolume(String),
+    AssetLastRate(String),
+    SeenHash(BytesN<32>),
+    UserOutcomes(Address),
+    LedgerSwapCount(u32),
+    UserMilestoneHit(Address),
+    AllowedApp(Symbol),
+    AppCount(Symbol),
+    AppVolume(Symbol),
+    DupFilter,
+    VolumeSumSquares,
+    PendingSwaps,
+    LastSwapTs,
+    AssetCodes,
+    UserFirstTs(Address),
+    CumulativeVolume(u64),
+    TotalQuoteValue,
+    DroppedCount,
+    Private(Address),
+    AssetDecimals(String),
+    AssetSupply(String),
+    StatusCount(SwapStatus),
 }
 
 #[contract]
@@ -27,162 +402,7249 @@ pub struct SwapTrackerContract;
 
 #[contractimpl]
 impl SwapTrackerContract {
-    /// Records a swap and emits a `swap_recorded` event.
-    ///
-    /// # Arguments
-    /// * `user` - The address of the user who performed the swap
-    /// * `from_asset` - The asset code being sold (e.g. "XLM")
-    /// * `to_asset` - The asset code being bought (e.g. "USDC")
-    /// * `amount` - The amount of the source asset swapped (in stroops / smallest unit)
-    /// * `timestamp` - Unix timestamp of the swap
-    pub fn record_swap(
-        env: Env,
-        user: Address,
-        from_asset: String,
-        to_asset: String,
-        amount: i128,
-        timestamp: u64,
-    ) {
-        // Get current swap count, defaulting to 0
-        let count: u64 = env
-            .storage()
-            .persistent()
-            .get(&DataKey::SwapCount)
-            .unwrap_or(0);
+    /// Sets the contract admin. Can only be called once, and never again
+    /// after the admin has been renounced.
+    pub fn initialize(env: Env, admin: Address) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        let storage = env.storage().instance();
+        if storage.has(&ConfigKey::Admin) || storage.has(&ConfigKey::AdminRenounced) {
+            return Err(Error::AlreadyInitialized);
+        }
+        env.storage().instance().set(&ConfigKey::Admin, &admin);
+        Ok(())
+    }
 
-        let record = SwapRecord {
-            user: user.clone(),
-            from_asset: from_asset.clone(),
-            to_asset: to_asset.clone(),
-            amount,
-            timestamp,
-        };
+    /// Permanently removes the admin (admin only). Every admin-gated
+    /// function fails with `Error::NoAdmin` afterwards. Irreversible.
+    pub fn renounce_admin(env: Env) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage().instance().remove(&ConfigKey::Admin);
+        env.storage()
+            .instance()
+            .set(&ConfigKey::AdminRenounced, &true);
+        Ok(())
+    }
+
+    /// Returns the current admin, if the contract has been initialized.
+    pub fn get_admin(env: Env) -> Option<Address> {
+        ensure_not_frozen(&env);
+        env.storage().instance().get(&ConfigKey::Admin)
+    }
 
-        // Store the swap record
+    /// Replaces the contract code with the uploaded wasm `new_wasm_hash`
+    /// (admin only). Storage is kept as-is.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.deployer().update_current_contract_wasm(new_wasm_hash);
+        Ok(())
+    }
+
+    /// Enables or disables aggregate maintenance in `record_swap` (admin only).
+    ///
+    /// While disabled, only the raw record is stored and the event emitted;
+    /// aggregate queries such as `get_total_volume` and `get_user_stats`
+    /// return stale values until aggregation is re-enabled.
+    pub fn set_aggregation_enabled(env: Env, enabled: bool) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
         env.storage()
-            .persistent()
-            .set(&DataKey::Swap(count), &record);
+            .instance()
+            .set(&ConfigKey::AggregationEnabled, &enabled);
+        Ok(())
+    }
 
-        // Increment and store the new count
-        let new_count = count + 1;
+    /// Returns whether `record_swap` maintains aggregates. Defaults to `true`.
+    pub fn is_aggregation_enabled(env: Env) -> bool {
+        ensure_not_frozen(&env);
         env.storage()
-            .persistent()
-            .set(&DataKey::SwapCount, &new_count);
+            .instance()
+            .get(&ConfigKey::AggregationEnabled)
+            .unwrap_or(true)
+    }
 
-        // Emit a contract event for real-time listeners
-        env.events().publish(
-            (symbol_short!("swap"),),
-            (user, from_asset, to_asset, amount, timestamp),
-        );
+    /// Pauses swap recording (admin only). Reads remain available.
+    pub fn pause(env: Env) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage().instance().set(&ConfigKey::Paused, &true);
+        Ok(())
     }
 
-    /// Returns the most recent `count` swap records, newest first.
+    /// Resumes swap recording (admin only).
+    pub fn unpause(env: Env) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage().instance().set(&ConfigKey::Paused, &false);
+        Ok(())
+    }
+
+    /// Returns whether swap recording is paused.
+    pub fn is_paused(env: Env) -> bool {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::Paused)
+            .unwrap_or(false)
+    }
+
+    /// Freezes the contract (admin only): every call other than `unfreeze`
+    /// and `is_frozen`, reads included, fails with `Error::Frozen`. Meant
+    /// for severe incidents; use `pause` to stop only recording.
+    pub fn freeze(env: Env) -> Result<(), Error> {
+        require_admin(&env)?;
+        env.storage().instance().set(&ConfigKey::Frozen, &true);
+        Ok(())
+    }
+
+    /// Lifts a `freeze` (admin only).
+    pub fn unfreeze(env: Env) -> Result<(), Error> {
+        require_admin(&env)?;
+        env.storage().instance().remove(&ConfigKey::Frozen);
+        Ok(())
+    }
+
+    /// Returns whether the contract is frozen.
+    pub fn is_frozen(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&ConfigKey::Frozen)
+            .unwrap_or(false)
+    }
+
+    /// Sets the circuit-breaker multiplier (admin only). `0` disables it.
     ///
-    /// # Arguments
-    /// * `count` - The maximum number of recent swaps to return
-    pub fn get_recent_swaps(env: Env, count: u32) -> Vec<SwapRecord> {
-        let total: u64 = env
-            .storage()
-            .persistent()
-            .get(&DataKey::SwapCount)
-            .unwrap_or(0);
+    /// When enabled, a swap whose amount exceeds `multiplier` times the
+    /// current average swap amount pauses the contract and emits a
+    /// `circuit_break` event. The offending swap is still recorded, since
+    /// returning an error would also roll back the pause.
+    pub fn set_circuit_multiplier(env: Env, multiplier: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::CircuitMultiplier, &multiplier);
+        Ok(())
+    }
 
-        let mut swaps = vec![&env];
-        let limit = if (count as u64) > total {
-            total
+    /// Returns the circuit-breaker multiplier (`0` when disabled).
+    pub fn get_circuit_multiplier(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::CircuitMultiplier)
+            .unwrap_or(0)
+    }
+
+    /// Adds or removes `category` from the set accepted by `record_swap`
+    /// (admin only).
+    pub fn set_category_allowed(env: Env, category: Symbol, allowed: bool) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        let key = DataKey::AllowedCategory(category);
+        if allowed {
+            env.storage().persistent().set(&key, &true);
         } else {
-            count as u64
-        };
+            env.storage().persistent().remove(&key);
+        }
+        Ok(())
+    }
 
-        // Iterate from newest to oldest
-        for i in 0..limit {
-            let index = total - 1 - i;
-            if let Some(record) = env
-                .storage()
-                .persistent()
-                .get::<DataKey, SwapRecord>(&DataKey::Swap(index))
-            {
-                swaps.push_back(record);
-            }
+    /// Returns whether `category` may be used when recording swaps.
+    pub fn is_category_allowed(env: Env, category: Symbol) -> bool {
+        ensure_not_frozen(&env);
+        env.storage()
+            .persistent()
+            .has(&DataKey::AllowedCategory(category))
+    }
+
+    /// Adds or removes `app_id` from the source applications accepted by
+    /// `record_swap` (admin only).
+    pub fn set_app_allowed(env: Env, app_id: Symbol, allowed: bool) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        let key = DataKey::AllowedApp(app_id);
+        if allowed {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
         }
+        Ok(())
+    }
 
-        swaps
+    /// Returns whether swaps may be attributed to `app_id`.
+    pub fn is_app_allowed(env: Env, app_id: Symbol) -> bool {
+        ensure_not_frozen(&env);
+        env.storage().persistent().has(&DataKey::AllowedApp(app_id))
     }
 
-    /// Returns the total number of swaps recorded.
-    pub fn get_swap_count(env: Env) -> u64 {
+    /// Turns pair allowlist enforcement on or off (admin only). While on,
+    /// only pairs approved with `approve_pair` may be recorded.
+    pub fn set_pair_allowlist_enabled(env: Env, enabled: bool) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::PairAllowlistEnabled, &enabled);
+        Ok(())
+    }
+
+    /// Returns whether the pair allowlist is enforced. Defaults to `false`.
+    pub fn is_pair_allowlist_enabled(env: Env) -> bool {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::PairAllowlistEnabled)
+            .unwrap_or(false)
+    }
+
+    /// Approves swaps selling `from` for `to` (admin only). The reverse
+    /// direction must be approved separately.
+    pub fn approve_pair(env: Env, from: String, to: String) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
         env.storage()
             .persistent()
-            .get(&DataKey::SwapCount)
-            .unwrap_or(0)
+            .set(&PairKey::Approved(from, to), &true);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use soroban_sdk::{testutils::Events, Env, IntoVal};
+    /// Withdraws approval for swaps selling `from` for `to` (admin only).
+    pub fn revoke_pair(env: Env, from: String, to: String) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .persistent()
+            .remove(&PairKey::Approved(from, to));
+        Ok(())
+    }
 
-    #[test]
-    fn test_record_and_retrieve() {
-        let env = Env::default();
-        let contract_id = env.register_contract(None, SwapTrackerContract);
-        let client = SwapTrackerContractClient::new(&env, &contract_id);
+    /// Returns whether swaps selling `from` for `to` have been approved.
+    pub fn is_pair_approved(env: Env, from: String, to: String) -> bool {
+        ensure_not_frozen(&env);
+        env.storage().persistent().has(&PairKey::Approved(from, to))
+    }
 
-        let user = Address::generate(&env);
-        let from = String::from_str(&env, "XLM");
-        let to = String::from_str(&env, "USDC");
+    /// Sets the window, in seconds, during which a user may not record the
+    /// same (from, to, amount) swap again (admin only). `0` disables it.
+    pub fn set_duplicate_window(env: Env, seconds: u64) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::DuplicateWindow, &seconds);
+        Ok(())
+    }
+
+    /// Returns the duplicate-swap window in seconds (`0` when disabled).
+    pub fn get_duplicate_window(env: Env) -> u64 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::DuplicateWindow)
+            .unwrap_or(0)
+    }
 
-        client.record_swap(&user, &from, &to, &1_000_000_i128, &1700000000_u64);
+    /// Turns strict per-user timestamp ordering on or off (admin only).
+    /// While on, a swap whose timestamp equals the user's previous swap
+    /// timestamp is rejected with `TimestampCollision`, whether or not
+    /// aggregation is enabled.
+    pub fn set_require_monotonic_ts(env: Env, enabled: bool) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::RequireMonotonicTs, &enabled);
+        Ok(())
+    }
 
-        assert_eq!(client.get_swap_count(), 1);
+    /// Returns whether duplicate per-user timestamps are rejected.
+    /// Defaults to `false`.
+    pub fn is_monotonic_ts_required(env: Env) -> bool {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::RequireMonotonicTs)
+            .unwrap_or(false)
+    }
 
-        let swaps = client.get_recent_swaps(&1);
-        assert_eq!(swaps.len(), 1);
+    /// Sets the minimum number of seconds, by ledger time, between two
+    /// swaps of the same user (admin only). `0` disables rate limiting.
+    pub fn set_min_swap_interval(env: Env, seconds: u64) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::MinSwapInterval, &seconds);
+        Ok(())
+    }
 
-        let record = swaps.get(0).unwrap();
-        assert_eq!(record.amount, 1_000_000);
-        assert_eq!(record.timestamp, 1700000000);
+    /// Returns the minimum swap interval in seconds (`0` when disabled).
+    pub fn get_min_swap_interval(env: Env) -> u64 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::MinSwapInterval)
+            .unwrap_or(0)
     }
 
-    #[test]
-    fn test_multiple_swaps_ordering() {
-        let env = Env::default();
-        let contract_id = env.register_contract(None, SwapTrackerContract);
-        let client = SwapTrackerContractClient::new(&env, &contract_id);
+    /// Returns the earliest ledger timestamp at which `user` may record
+    /// their next swap under the rate limit, or `0` if they can swap now.
+    pub fn next_allowed_swap(env: Env, user: Address) -> u64 {
+        ensure_not_frozen(&env);
+        let interval = Self::get_min_swap_interval(env.clone());
+        if interval == 0 {
+            return 0;
+        }
+        let last: Option<(BytesN<32>, u64)> =
+            env.storage().persistent().get(&DataKey::UserLastSwap(user));
+        match last {
+            Some((_, last_ts)) if env.ledger().timestamp() < last_ts.saturating_add(interval) => {
+                last_ts.saturating_add(interval)
+            }
+            _ => 0,
+        }
+    }
 
-        let user = Address::generate(&env);
-        let xlm = String::from_str(&env, "XLM");
-        let usdc = String::from_str(&env, "USDC");
+    /// Sets the contract notified with `on_swap(record)` after every
+    /// recorded swap, or clears it with `None` (admin only).
+    pub fn set_hook_contract(env: Env, hook: Option<Address>) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        let storage = env.storage().instance();
+        match hook {
+            Some(hook) => storage.set(&ConfigKey::HookContract, &hook),
+            None => storage.remove(&ConfigKey::HookContract),
+        }
+        Ok(())
+    }
 
-        // Record 3 swaps
-        client.record_swap(&user, &xlm, &usdc, &100_i128, &1000_u64);
-        client.record_swap(&user, &usdc, &xlm, &200_i128, &2000_u64);
-        client.record_swap(&user, &xlm, &usdc, &300_i128, &3000_u64);
+    /// Returns the swap hook contract, if one is set.
+    pub fn get_hook_contract(env: Env) -> Option<Address> {
+        ensure_not_frozen(&env);
+        env.storage().instance().get(&ConfigKey::HookContract)
+    }
 
-        assert_eq!(client.get_swap_count(), 3);
+    /// Sets how many swaps the contract accepts per ledger (admin only).
+    /// Further swaps in the same ledger fail with `Error::LedgerThrottled`.
+    /// `0` disables the cap.
+    pub fn set_max_swaps_per_ledger(env: Env, max: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::MaxSwapsPerLedger, &max);
+        Ok(())
+    }
 
-        // Get last 2 — should be newest first
-        let swaps = client.get_recent_swaps(&2);
-        assert_eq!(swaps.len(), 2);
-        assert_eq!(swaps.get(0).unwrap().amount, 300);
-        assert_eq!(swaps.get(1).unwrap().amount, 200);
+    /// Returns the per-ledger swap cap (`0` when disabled).
+    pub fn get_max_swaps_per_ledger(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::MaxSwapsPerLedger)
+            .unwrap_or(0)
     }
 
-    #[test]
-    fn test_event_emission() {
-        let env = Env::default();
-        let contract_id = env.register_contract(None, SwapTrackerContract);
-        let client = SwapTrackerContractClient::new(&env, &contract_id);
+    /// Sets how many shards `swap` events are spread over (admin only).
+    /// Each event carries `index % shards` as its third topic so indexer
+    /// workers can split the stream. Must be at least `1`.
+    pub fn set_event_shards(env: Env, shards: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if shards == 0 {
+            return Err(Error::InvalidShardCount);
+        }
+        env.storage()
+            .instance()
+            .set(&ConfigKey::EventShards, &shards);
+        Ok(())
+    }
 
-        let user = Address::generate(&env);
-        let from = String::from_str(&env, "XLM");
-        let to = String::from_str(&env, "USDC");
+    /// Returns the number of `swap` event shards. Defaults to `1`.
+    pub fn get_event_shards(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::EventShards)
+            .unwrap_or(1)
+    }
+
+    /// Sets how many ledgers an idempotency key is remembered for (admin
+    /// only). `0` disables the check.
+    ///
+    /// Each recorded swap's key, a hash of `(user, from_asset, to_asset,
+    /// amount, timestamp)`, is kept in temporary storage with this TTL, so
+    /// replays are rejected with `Error::DuplicateSwap` until it expires.
+    /// The TTL is clamped to the network's maximum, and the network's
+    /// minimum temporary TTL still applies.
+    pub fn set_idempotency_ttl(env: Env, ledgers: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::IdempotencyTtl, &ledgers);
+        Ok(())
+    }
 
-        client.record_swap(&user, &from, &to, &500_i128, &1700000000_u64);
+    /// Returns the idempotency key TTL in ledgers (`0` when disabled).
+    pub fn get_idempotency_ttl(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::IdempotencyTtl)
+            .unwrap_or(0)
+    }
 
-        // Verify event was emitted
-        let events = env.events().all();
-        assert!(!events.is_empty());
+    /// Sets how many confirmations a swap needs before it is marked
+    /// `Completed` (admin only). With `0`, swaps are recorded as completed.
+    pub fn set_confirmation_threshold(env: Env, threshold: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::ConfirmationThreshold, &threshold);
+        Ok(())
+    }
+
+    /// Returns the confirmation threshold (`0` when disabled).
+    pub fn get_confirmation_threshold(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::ConfirmationThreshold)
+            .unwrap_or(0)
+    }
+
+    /// Adds an external confirmation to a pending swap (admin only).
+    ///
+    /// Once the confirmation threshold is reached the swap transitions to
+    /// `Completed` and a `confirmed` event is emitted.
+    pub fn add_confirmation(env: Env, index: u64) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+
+        let mut record = read_swap(&env, index).ok_or(Error::SwapNotFound)?;
+        if record.status != SwapStatus::Pending {
+            return Err(Error::NotPending);
+        }
+
+        record.confirmations += 1;
+        if record.confirmations >= Self::get_confirmation_threshold(env.clone()) {
+            change_status(&env, index, &mut record, SwapStatus::Completed);
+            publish_event(
+                &env,
+                symbol_short!("confirmed"),
+                (index, record.confirmations),
+            );
+        }
+
+        write_swap(&env, index, &record);
+        Ok(())
+    }
+
+    /// Marks a pending swap as `Failed` (admin only) and emits a `failed`
+    /// event.
+    pub fn fail_swap(env: Env, index: u64) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+
+        let mut record = read_swap(&env, index).ok_or(Error::SwapNotFound)?;
+        if record.status != SwapStatus::Pending {
+            return Err(Error::NotPending);
+        }
+
+        change_status(&env, index, &mut record, SwapStatus::Failed);
+        write_swap(&env, index, &record);
+        publish_event(&env, symbol_short!("failed"), index);
+        Ok(())
+    }
+
+    /// Sets the status of every swap in `indices` to `status` (admin only),
+    /// emitting a `status_changed` event with `(index, status)` for each
+    /// record that changed. Missing indices and records already in
+    /// `status` are skipped. Returns how many records changed, or
+    /// `Error::BatchTooLarge` if `indices` exceeds the maximum batch size.
+    pub fn set_statuses_batch(
+        env: Env,
+        indices: Vec<u64>,
+        status: SwapStatus,
+    ) -> Result<u32, Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if indices.len() > Self::get_max_batch_size(env.clone()) {
+            return Err(Error::BatchTooLarge);
+        }
+
+        let mut updated: u32 = 0;
+        for index in indices.iter() {
+            let Some(mut record) = read_swap(&env, index) else {
+                continue;
+            };
+            if record.status == status {
+                continue;
+            }
+            change_status(&env, index, &mut record, status);
+            write_swap(&env, index, &record);
+            publish_event(&env, Symbol::new(&env, "status_changed"), (index, status));
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
+    /// Returns how many stored records currently have `status`.
+    pub fn get_status_count(env: Env, status: SwapStatus) -> u64 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::StatusCount(status)).unwrap_or(0)
+    }
+
+    /// Marks up to `limit` pending swaps whose deadline has passed as
+    /// `Failed`, oldest first, emitting an `expired` event for each.
+    /// Anyone may call this. Returns how many swaps were expired.
+    pub fn expire_stale_swaps(env: Env, limit: u32) -> u32 {
+        ensure_not_frozen(&env);
+        let now = env.ledger().timestamp();
+        let mut expired: u32 = 0;
+
+        for index in pending_swaps(&env).iter() {
+            if expired >= limit {
+                break;
+            }
+            let Some(mut record) = read_swap(&env, index) else {
+                continue;
+            };
+            if record.deadline_ts == 0 || now <= record.deadline_ts {
+                continue;
+            }
+
+            change_status(&env, index, &mut record, SwapStatus::Failed);
+            write_swap(&env, index, &record);
+            publish_event(&env, symbol_short!("expired"), index);
+            expired += 1;
+        }
+
+        expired
+    }
+
+    /// Returns up to `limit` `Pending` swaps whose timestamp is more than
+    /// `older_than_secs` before the ledger timestamp, newest first.
+    pub fn get_stale_pending(env: Env, older_than_secs: u64, limit: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let now = env.ledger().timestamp();
+        let mut swaps = vec![&env];
+
+        for index in pending_swaps(&env).iter().rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                if now.saturating_sub(record.timestamp) > older_than_secs {
+                    swaps.push_back(record);
+                }
+            }
+        }
+
+        swaps
+    }
+
+    /// Sets the global minimum swap amount (admin only).
+    pub fn set_min_amount(env: Env, min: i128) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage().instance().set(&ConfigKey::MinAmount, &min);
+        Ok(())
+    }
+
+    /// Returns the global minimum swap amount. Defaults to `0`.
+    pub fn get_min_amount(env: Env) -> i128 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::MinAmount)
+            .unwrap_or(0)
+    }
+
+    /// Sets the minimum amount for swaps selling `asset` (admin only),
+    /// overriding the global minimum for that asset.
+    pub fn set_asset_min(env: Env, asset: String, min: i128) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .persistent()
+            .set(&DataKey::AssetMin(asset), &min);
+        Ok(())
+    }
+
+    /// Returns the minimum amount enforced for swaps selling `asset`,
+    /// falling back to the global minimum when no asset floor is set.
+    pub fn get_asset_min(env: Env, asset: String) -> i128 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .persistent()
+            .get(&DataKey::AssetMin(asset))
+            .unwrap_or_else(|| Self::get_min_amount(env.clone()))
+    }
+
+    /// Flags the swap at `index` for compliance review (admin only) and
+    /// emits a `flagged` event. The record itself is left unchanged;
+    /// flagging an already flagged swap replaces its reason.
+    pub fn flag_swap(env: Env, index: u64, reason: Symbol) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if read_swap(&env, index).is_none() {
+            return Err(Error::SwapNotFound);
+        }
+
+        let storage = env.storage().persistent();
+        let key = DataKey::SwapFlag(index);
+        if !storage.has(&key) {
+            let mut flagged: Vec<u64> = storage.get(&DataKey::FlaggedSwaps).unwrap_or(vec![&env]);
+            flagged.push_back(index);
+            storage.set(&DataKey::FlaggedSwaps, &flagged);
+        }
+        storage.set(&key, &reason);
+
+        publish_event(&env, symbol_short!("flagged"), (index, reason));
+        Ok(())
+    }
+
+    /// Returns the flag reason for the swap at `index`, if flagged.
+    pub fn get_swap_flag(env: Env, index: u64) -> Option<Symbol> {
+        ensure_not_frozen(&env);
+        env.storage().persistent().get(&DataKey::SwapFlag(index))
+    }
+
+    /// Returns up to `limit` flagged swaps, most recently flagged first.
+    pub fn get_flagged_swaps(env: Env, limit: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let flagged: Vec<u64> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::FlaggedSwaps)
+            .unwrap_or(vec![&env]);
+
+        let mut swaps = vec![&env];
+        for index in flagged.iter().rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                swaps.push_back(record);
+            }
+        }
+        swaps
+    }
+
+    /// Blocks `addr` from recording swaps (admin only). Its existing
+    /// records remain readable.
+    pub fn block_address(env: Env, addr: Address) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .persistent()
+            .set(&DataKey::Blocked(addr), &true);
+        Ok(())
+    }
+
+    /// Lifts a block on `addr` (admin only).
+    pub fn unblock_address(env: Env, addr: Address) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage().persistent().remove(&DataKey::Blocked(addr));
+        Ok(())
+    }
+
+    /// Returns whether `addr` is blocked from recording swaps.
+    pub fn is_blocked(env: Env, addr: Address) -> bool {
+        ensure_not_frozen(&env);
+        env.storage().persistent().has(&DataKey::Blocked(addr))
+    }
+
+    /// Sets the swap-count interval at which a `milestone` event is
+    /// emitted (admin only). `0` disables milestone events.
+    pub fn set_milestone_interval(env: Env, interval: u64) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::MilestoneInterval, &interval);
+        Ok(())
+    }
+
+    /// Returns the milestone interval (`0` when disabled).
+    pub fn get_milestone_interval(env: Env) -> u64 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::MilestoneInterval)
+            .unwrap_or(0)
+    }
+
+    /// Sets the per-user cumulative volume at which a `user_milestone`
+    /// event is emitted (admin only). `0` disables it.
+    ///
+    /// The event fires once per user and threshold, with `(user, volume)`
+    /// as its payload, while aggregation is enabled.
+    pub fn set_user_milestone(env: Env, volume: i128) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::UserMilestone, &volume);
+        Ok(())
+    }
+
+    /// Returns the per-user volume milestone (`0` when disabled).
+    pub fn get_user_milestone(env: Env) -> i128 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::UserMilestone)
+            .unwrap_or(0)
+    }
+
+    /// Sets the TTL policy for swap records (admin only): whenever a record
+    /// is written with fewer than `threshold` ledgers to live, its TTL is
+    /// extended to `extend_to` ledgers, capped at the network maximum.
+    /// `threshold` may not exceed `extend_to`.
+    pub fn set_ttl_policy(env: Env, threshold: u32, extend_to: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if threshold > extend_to {
+            return Err(Error::InvalidTtl);
+        }
+        let storage = env.storage().instance();
+        storage.set(&ConfigKey::TtlThreshold, &threshold);
+        storage.set(&ConfigKey::TtlExtendTo, &extend_to);
+        Ok(())
+    }
+
+    /// Returns the record TTL policy as `(threshold, extend_to)` in
+    /// ledgers. Defaults to about one day and 30 days.
+    pub fn get_ttl_policy(env: Env) -> (u32, u32) {
+        ensure_not_frozen(&env);
+        let storage = env.storage().instance();
+        (
+            storage
+                .get(&ConfigKey::TtlThreshold)
+                .unwrap_or(DEFAULT_TTL_THRESHOLD),
+            storage
+                .get(&ConfigKey::TtlExtendTo)
+                .unwrap_or(DEFAULT_TTL_EXTEND_TO),
+        )
+    }
+
+    /// Sets how derived rates (averages, candles, ticks) are rounded
+    /// (admin only). Stored candles and ticks keep the mode they were
+    /// computed with until `rebuild_aggregates` runs.
+    pub fn set_rounding_mode(env: Env, mode: RoundingMode) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::RoundingMode, &mode);
+        Ok(())
+    }
+
+    /// Returns the rate rounding mode. Defaults to `Floor`.
+    pub fn get_rounding_mode(env: Env) -> RoundingMode {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::RoundingMode)
+            .unwrap_or(RoundingMode::Floor)
+    }
+
+    /// Sets the fixed-point scale applied to derived rates and asset prices
+    /// (admin only). Must be positive.
+    ///
+    /// Stored rates are not rescaled: candles, last rates and ticks keep
+    /// the scale they were computed with until `rebuild_aggregates` runs,
+    /// and configured asset prices must be updated by the admin.
+    pub fn set_rate_scale(env: Env, scale: i128) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if scale <= 0 {
+            return Err(Error::InvalidRateScale);
+        }
+        env.storage().instance().set(&ConfigKey::RateScale, &scale);
+        Ok(())
+    }
+
+    /// Returns the fixed-point scale of rate values. Defaults to `10^7`.
+    pub fn get_rate_scale(env: Env) -> i128 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::RateScale)
+            .unwrap_or(DEFAULT_RATE_SCALE)
+    }
+
+    /// Records a swap and emits a `swap_recorded` event.
+    ///
+    /// # Arguments
+    /// * `user` - The address of the user who performed the swap
+    /// * `from_asset` - The asset code being sold (e.g. "XLM")
+    /// * `to_asset` - The asset code being bought (e.g. "USDC")
+    /// * `amount` - The amount of the source asset swapped (in stroops / smallest unit)
+    /// * `to_amount` - The amount of the destination asset received (smallest unit)
+    /// * `timestamp` - Unix timestamp of the swap
+    /// * `category` - Analytics category (e.g. `arbitrage`), must be allowed by the admin
+    /// * `parent_id` - Optional id of the parent order when this swap is a partial fill
+    /// * `app_id` - Source application of the swap, must be allowed by the admin
+    /// * `deadline_ts` - Ledger timestamp after which the swap expires if still pending (`0` for none)
+    ///
+    /// The current ledger sequence is stored on the record and included in
+    /// the event payload, followed by the pair's tick direction (`Flat`
+    /// while aggregation is disabled).
+    ///
+    /// Returns the index assigned to the swap, which equals the swap count
+    /// before the call. Use it with `get_swap`, or read the record's
+    /// content-derived `swap_id`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_swap(
+        env: Env,
+        user: Address,
+        from_asset: String,
+        to_asset: String,
+        amount: i128,
+        to_amount: i128,
+        timestamp: u64,
+        category: Symbol,
+        parent_id: Option<BytesN<32>>,
+        app_id: Symbol,
+        deadline_ts: u64,
+    ) -> Result<u64, Error> {
+        ensure_not_frozen(&env);
+        store_swap(
+            &env,
+            SwapInput {
+                user,
+                from_asset,
+                to_asset,
+                amount,
+                to_amount,
+                timestamp,
+                category,
+                parent_id: parent_id.map(Into::into),
+                app_id,
+                deadline_ts,
+            },
+        )
+    }
+
+    /// Returns the most recent `count` swap records, newest first.
+    ///
+    /// # Arguments
+    /// * `count` - The maximum number of recent swaps to return
+    pub fn get_recent_swaps(env: Env, count: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let total: u64 = env
+            .storage()
+            .persistent()
+            .get(&DataKey::SwapCount)
+            .unwrap_or(0);
+
+        let mut swaps = vec![&env];
+        let limit = if (count as u64) > total {
+            total
+        } else {
+            count as u64
+        };
+
+        // Iterate from newest to oldest
+        for i in 0..limit {
+            let index = total - 1 - i;
+            if let Some(record) = read_public_swap(&env, index) {
+                swaps.push_back(record);
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns the most recent `count` swap records, newest first, each
+    /// paired with its flag reason if it has been flagged.
+    pub fn get_recent_swaps_with_flags(env: Env, count: u32) -> Vec<(SwapRecord, Option<Symbol>)> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let mut swaps = vec![&env];
+
+        for index in (0..total).rev().take(count as usize) {
+            if let Some(record) = read_public_swap(&env, index) {
+                swaps.push_back((record, Self::get_swap_flag(env.clone(), index)));
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns the most recent `count` swap records, newest first, each
+    /// paired with its age in seconds relative to the ledger timestamp.
+    /// Records dated in the future have an age of `0`.
+    pub fn get_recent_swaps_with_age(env: Env, count: u32) -> Vec<(SwapRecord, u64)> {
+        ensure_not_frozen(&env);
+        let now = env.ledger().timestamp();
+        let total = Self::get_swap_count(env.clone());
+        let mut swaps = vec![&env];
+
+        for index in (0..total).rev().take(count as usize) {
+            if let Some(record) = read_public_swap(&env, index) {
+                let age = now.saturating_sub(record.timestamp);
+                swaps.push_back((record, age));
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns the distinct users behind the most recent `count` swaps,
+    /// newest first. A repeat trader appears once, at their latest swap.
+    /// Private users are listed under the placeholder address.
+    pub fn get_recent_traders(env: Env, count: u32) -> Vec<Address> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let mut traders: Vec<Address> = vec![&env];
+
+        for index in (0..total).rev().take(count as usize) {
+            if let Some(record) = read_public_swap(&env, index) {
+                if !traders.contains(&record.user) {
+                    traders.push_back(record.user);
+                }
+            }
+        }
+
+        traders
+    }
+
+    /// Returns a page of up to `limit` swap records, newest first.
+    ///
+    /// Pass `None` to start from the newest record, then feed each
+    /// returned `next_cursor` back in until it comes back as `None`.
+    pub fn get_cursor_page(env: Env, cursor: Option<u64>, limit: u32) -> CursorPage {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+
+        // `pos` is the exclusive upper bound of the indices still to read
+        let mut pos = match cursor {
+            Some(index) if index < total => index + 1,
+            _ => total,
+        };
+
+        let mut records = vec![&env];
+        while pos > 0 && records.len() < limit {
+            pos -= 1;
+            if let Some(record) = read_public_swap(&env, pos) {
+                records.push_back(record);
+            }
+        }
+
+        let next_cursor = if pos > 0 { Some(pos - 1) } else { None };
+        CursorPage {
+            records,
+            next_cursor,
+            has_more: next_cursor.is_some(),
+        }
+    }
+
+    /// Exports the records with indices in `[start, end)` as packed bytes.
+    ///
+    /// Layout: a big-endian `u32` record count, followed by one entry per
+    /// stored record consisting of a big-endian `u32` byte length and the
+    /// XDR-encoded `SwapRecord`. Missing indices are skipped, `end` is
+    /// clamped to `SwapCount` and at most 100 indices are read per call.
+    pub fn export_range(env: Env, start: u64, end: u64) -> Bytes {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let end = end.min(total).min(start.saturating_add(MAX_EXPORT_RANGE));
+
+        let mut body = Bytes::new(&env);
+        let mut exported: u32 = 0;
+        for index in start..end {
+            if let Some(record) = read_public_swap(&env, index) {
+                let encoded = record.to_xdr(&env);
+                body.extend_from_array(&encoded.len().to_be_bytes());
+                body.append(&encoded);
+                exported += 1;
+            }
+        }
+
+        let mut out = Bytes::from_array(&env, &exported.to_be_bytes());
+        out.append(&body);
+        out
+    }
+
+    /// Returns the swap record stored at `index`, if any.
+    pub fn get_swap(env: Env, index: u64) -> Option<SwapRecord> {
+        ensure_not_frozen(&env);
+        read_public_swap(&env, index)
+    }
+
+    /// Returns the swap with the given content-derived id, if any.
+    pub fn get_swap_by_id(env: Env, swap_id: BytesN<32>) -> Option<SwapRecord> {
+        ensure_not_frozen(&env);
+        let index: u64 = env
+            .storage()
+            .persistent()
+            .get(&DataKey::SwapById(swap_id))?;
+        read_public_swap(&env, index)
+    }
+
+    /// Returns the nearest stored record below `index`, skipping missing
+    /// ones, or `None` if there is none.
+    pub fn get_previous_swap(env: Env, index: u64) -> Option<SwapRecord> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let oldest = Self::get_dropped_count(env.clone());
+        (oldest..index.min(total))
+            .rev()
+            .find_map(|previous| read_public_swap(&env, previous))
+    }
+
+    /// Returns the nearest stored record above `index`, skipping missing
+    /// ones, or `None` if there is none.
+    pub fn get_next_swap(env: Env, index: u64) -> Option<SwapRecord> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        (index.saturating_add(1)..total).find_map(|next| read_public_swap(&env, next))
+    }
+
+    /// Returns the total number of swaps recorded.
+    pub fn get_swap_count(env: Env) -> u64 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .persistent()
+            .get(&DataKey::SwapCount)
+            .unwrap_or(0)
+    }
+
+    /// Returns the swap count, newest swap timestamp, number of distinct
+    /// assets and pause flag in one call, without scanning records.
+    pub fn get_summary(env: Env) -> Summary {
+        ensure_not_frozen(&env);
+        Summary {
+            total_swaps: Self::get_swap_count(env.clone()),
+            last_timestamp: env
+                .storage()
+                .persistent()
+                .get(&DataKey::LastSwapTs)
+                .unwrap_or(0),
+            distinct_assets: asset_list(&env).len(),
+            paused: Self::is_paused(env),
+        }
+    }
+
+    /// Returns the total amount swapped across all users.
+    pub fn get_total_volume(env: Env) -> i128 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::TotalVolume).unwrap_or(0)
+    }
+
+    /// Returns the cumulative volume after each of the most recent `count`
+    /// swaps, oldest to newest.
+    ///
+    /// Cumulative totals are stored by `record_swap` as each swap is
+    /// written. Imported and corrected records are not reflected in them.
+    pub fn get_cumulative_series(env: Env, count: u32) -> Vec<i128> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let mut series = vec![&env];
+
+        for index in (0..total).rev().take(count as usize) {
+            if let Some(cumulative) = cumulative_volume(&env, index) {
+                series.push_front(cumulative);
+            }
+        }
+
+        series
+    }
+
+    /// Returns the volume of the swaps after `start_index` up to and
+    /// including `end_index`, from the stored cumulative totals.
+    ///
+    /// Returns `Error::InvalidRange` unless `start_index <= end_index <
+    /// SwapCount`, and `Error::SwapNotFound` if either end has no stored
+    /// total (e.g. an imported record that filled a gap). Totals run on
+    /// across gaps left by `import_swap` and `set_swap_count`.
+    pub fn volume_between(env: Env, start_index: u64, end_index: u64) -> Result<i128, Error> {
+        ensure_not_frozen(&env);
+        if start_index > end_index || end_index >= Self::get_swap_count(env.clone()) {
+            return Err(Error::InvalidRange);
+        }
+        let start = cumulative_volume(&env, start_index).ok_or(Error::SwapNotFound)?;
+        let end = cumulative_volume(&env, end_index).ok_or(Error::SwapNotFound)?;
+        Ok(end - start)
+    }
+
+    /// Returns the total quote-currency value of all swaps, summed from
+    /// each record's `quote_value`.
+    pub fn get_total_quote_value(env: Env) -> i128 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::TotalQuoteValue).unwrap_or(0)
+    }
+
+    /// Recomputes the hash chain from the oldest live record through
+    /// `up_to_index` and returns whether every record links to the hash
+    /// of the one before it. Records rewritten after recording, including
+    /// through `correct_swap`, break the chain. The newest record is
+    /// checked against the stored chain head. Returns `false` if any
+    /// record in the range is missing.
+    pub fn verify_chain(env: Env, up_to_index: u64) -> bool {
+        ensure_not_frozen(&env);
+        let start = Self::get_dropped_count(env.clone());
+        if up_to_index < start || up_to_index >= Self::get_swap_count(env.clone()) {
+            return false;
+        }
+
+        // The first live record's link is trusted when older ones have
+        // been dropped, as there is nothing left to check it against
+        let mut expected = (start == 0).then(|| BytesN::from_array(&env, &[0; 32]));
+        for index in start..=up_to_index {
+            let Some(record) = read_swap(&env, index) else {
+                return false;
+            };
+            if expected.is_some_and(|hash| hash != record.prev_hash) {
+                return false;
+            }
+            expected = Some(record_hash(&env, &record));
+        }
+
+        // Nothing links to the newest record, so check it against the head
+        if up_to_index == Self::get_swap_count(env.clone()) - 1 {
+            return expected == Some(chain_head(&env));
+        }
+        true
+    }
+
+    /// Converts a whole-unit amount into the asset's smallest unit by
+    /// scaling it by `10^decimals`, e.g. `to_stroops(5, 7) == 50_000_000`.
+    ///
+    /// Returns `Error::Overflow` if the result does not fit in an `i128`.
+    pub fn to_stroops(env: Env, whole: i128, decimals: u32) -> Result<i128, Error> {
+        ensure_not_frozen(&env);
+        10_i128
+            .checked_pow(decimals)
+            .and_then(|scale| whole.checked_mul(scale))
+            .ok_or(Error::Overflow)
+    }
+
+    /// Returns the largest single swap amount recorded.
+    pub fn get_max_swap_amount(env: Env) -> i128 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::MaxAmount).unwrap_or(0)
+    }
+
+    /// Checks core storage invariants with a bounded number of reads.
+    ///
+    /// Verifies that the newest record sits at `SwapCount - 1` with nothing
+    /// stored past it, and that the global aggregates are non-negative and
+    /// mutually consistent. Records are not scanned.
+    pub fn health_check(env: Env) -> bool {
+        ensure_not_frozen(&env);
+        let count = Self::get_swap_count(env.clone());
+        let storage = env.storage().persistent();
+
+        if storage.has(&DataKey::Swap(count)) {
+            return false;
+        }
+        if count > 0 && !storage.has(&DataKey::Swap(count - 1)) {
+            return false;
+        }
+
+        let total = Self::get_total_volume(env.clone());
+        let max = Self::get_max_swap_amount(env);
+        total >= 0 && max >= 0 && max <= total
+    }
+
+    /// Recomputes every aggregate from the stored swap records (admin only).
+    ///
+    /// All aggregates touched by the stored records are zeroed and then
+    /// rebuilt by scanning every `DataKey::Swap(i)`. This is expensive and is
+    /// intended as a one-shot repair after aggregation was disabled or a
+    /// total was corrupted. The cumulative totals behind `volume_between`
+    /// are rewritten for every stored record. Records rolled off by
+    /// `set_max_records` are gone, so their contribution is lost for good.
+    pub fn rebuild_aggregates(env: Env) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+
+        let total = Self::get_swap_count(env.clone());
+        let storage = env.storage().persistent();
+        storage.remove(&DataKey::TotalVolume);
+        storage.remove(&DataKey::MaxAmount);
+        storage.remove(&DataKey::AmountHistogram);
+        storage.remove(&DayKey::Active);
+        storage.remove(&DayKey::ActiveCount);
+        storage.remove(&DataKey::VolumeSumSquares);
+        storage.remove(&DayKey::Busiest);
+        storage.remove(&DataKey::TotalQuoteValue);
+
+        // Listed assets may hold totals folded in by `merge_asset` without
+        // appearing in any record
+        for asset in asset_list(&env).iter().chain(asset_codes(&env).iter()) {
+            clear_asset_aggregates(&env, &asset);
+        }
+        for i in 0..total {
+            if let Some(record) = read_swap(&env, i) {
+                clear_aggregates(&env, &record);
+            }
+        }
+
+        let mut running: i128 = 0;
+        for i in 0..total {
+            if let Some(record) = read_swap(&env, i) {
+                register_asset(&env, &record.from_asset);
+                register_asset(&env, &record.to_asset);
+                update_aggregates(&env, &record)?;
+                running = running.saturating_add(record.amount);
+                storage.set(&DataKey::CumulativeVolume(i), &running);
+            }
+        }
+        env.storage()
+            .instance()
+            .set(&ConfigKey::RunningVolume, &running);
+
+        Ok(())
+    }
+
+    /// Clears the per-asset aggregates of `asset` (volume, flow counts,
+    /// fees and last rate) and the rate aggregates of every pair it trades
+    /// in (admin only), then emits an `asset_reset` event.
+    ///
+    /// Raw records, other assets and daily candles are left untouched; a
+    /// later `rebuild_aggregates` recomputes everything from the records.
+    pub fn reset_asset_aggregates(env: Env, asset: String) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+
+        let storage = env.storage().persistent();
+        clear_asset_aggregates(&env, &asset);
+
+        for other in asset_list(&env).iter() {
+            for (from, to) in [(asset.clone(), other.clone()), (other, asset.clone())] {
+                storage.remove(&PairKey::LastRate(from.clone(), to.clone()));
+                storage.remove(&PairKey::Tick(from.clone(), to.clone()));
+                storage.remove(&PairKey::RateMin(from.clone(), to.clone()));
+                storage.remove(&PairKey::RateMax(from, to));
+            }
+        }
+
+        publish_event(&env, Symbol::new(&env, "asset_reset"), asset);
+        Ok(())
+    }
+
+    /// Returns whether the maintained volume of `asset` matches the sum of
+    /// the raw records selling it. A cheaper spot check than
+    /// `rebuild_aggregates`; merged assets are expected to disagree.
+    ///
+    /// Also reports `false` without any corruption once records have rolled
+    /// off under `set_max_records` (aggregates keep their amounts but the
+    /// scan no longer sees them), and while aggregation is disabled (the
+    /// scan sees records the aggregates skipped). A sum that overflows
+    /// `i128` can't match a maintained total and reports `false`.
+    pub fn verify_volume(env: Env, asset: String) -> bool {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let mut scanned: i128 = 0;
+        for i in 0..total {
+            if let Some(record) = read_swap(&env, i) {
+                if record.from_asset == asset {
+                    scanned = match scanned.checked_add(record.amount) {
+                        Some(sum) => sum,
+                        None => return false,
+                    };
+                }
+            }
+        }
+
+        let maintained: i128 = read_aggregate(&env, &DataKey::AssetVolume(asset)).unwrap_or(0);
+        scanned == maintained
+    }
+
+    /// Enables or disables `import_swap` (admin only).
+    pub fn set_import_mode(env: Env, enabled: bool) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::ImportMode, &enabled);
+        Ok(())
+    }
+
+    /// Returns whether `import_swap` is enabled. Defaults to `false`.
+    pub fn is_import_mode(env: Env) -> bool {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::ImportMode)
+            .unwrap_or(false)
+    }
+
+    /// Enables or disables maintenance mode (admin only), which unlocks
+    /// `set_swap_count`.
+    pub fn set_maintenance_mode(env: Env, enabled: bool) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::MaintenanceMode, &enabled);
+        Ok(())
+    }
+
+    /// Returns whether maintenance mode is on. Defaults to `false`.
+    pub fn is_maintenance_mode(env: Env) -> bool {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::MaintenanceMode)
+            .unwrap_or(false)
+    }
+
+    /// Overwrites the swap count (admin only), to bring it back in line
+    /// with the stored records after an import or manual repair. Only
+    /// allowed in maintenance mode, otherwise `Error::MaintenanceDisabled`.
+    ///
+    /// The next swap must not land on a stored record, so a count is
+    /// rejected with `Error::IndexTaken` if `Swap(count)` is occupied or,
+    /// when lowering, any index between it and the old count is. Counts below
+    /// `get_dropped_count` are rejected with `Error::InvalidRange`. The
+    /// hash chain is relinked to the record at `count - 1`; other records
+    /// and aggregates are left as they are. Emits a `count_adjusted` event
+    /// with the old and new count.
+    pub fn set_swap_count(env: Env, count: u64) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if !Self::is_maintenance_mode(env.clone()) {
+            return Err(Error::MaintenanceDisabled);
+        }
+        if count < Self::get_dropped_count(env.clone()) {
+            return Err(Error::InvalidRange);
+        }
+
+        let previous = Self::get_swap_count(env.clone());
+        let storage = env.storage().persistent();
+        if storage.has(&DataKey::Swap(count))
+            || (count..previous).any(|index| storage.has(&DataKey::Swap(index)))
+        {
+            return Err(Error::IndexTaken);
+        }
+
+        // Seed the running total from the old count before it moves
+        add_running_volume(&env, 0);
+        storage.set(&DataKey::SwapCount, &count);
+        match count.checked_sub(1).and_then(|last| read_swap(&env, last)) {
+            Some(last) => env
+                .storage()
+                .instance()
+                .set(&ConfigKey::ChainHead, &record_hash(&env, &last)),
+            None => env.storage().instance().remove(&ConfigKey::ChainHead),
+        }
+        publish_event(&env, Symbol::new(&env, "count_adjusted"), (previous, count));
+        Ok(())
+    }
+
+    /// Returns the layout version of stored swap records. Deployments that
+    /// predate schema versioning report `1` until `migrate_schema` has
+    /// converted every record.
+    pub fn schema_version(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::SchemaVersion)
+            .unwrap_or(1)
+    }
+
+    /// Rewrites up to `limit` records from `start` that are still stored in
+    /// the legacy layout into the current one (admin only), returning how
+    /// many were rewritten. Fields the legacy layout lacked get the
+    /// defaults `read_swap` already reports for them.
+    ///
+    /// Run it over consecutive ranges; once a call reaches the end of the
+    /// swap history with every record in its range converted, the schema
+    /// version is set to the current one. Entries that decode as neither
+    /// layout hold the version back.
+    pub fn migrate_schema(env: Env, start: u64, limit: u32) -> Result<u32, Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        let total = Self::get_swap_count(env.clone());
+        let end = total.min(start.saturating_add(limit as u64));
+
+        let mut migrated: u32 = 0;
+        let mut complete = true;
+        for index in start..end {
+            let raw: Option<Val> = env.storage().persistent().get(&DataKey::Swap(index));
+            let Some(raw) = raw else {
+                continue;
+            };
+            if !is_legacy_layout(&env, &raw) {
+                complete &= StoredSwap::try_from_val(&env, &raw).is_ok();
+                continue;
+            }
+            match LegacySwap::try_from_val(&env, &raw) {
+                Ok(legacy) => {
+                    write_swap(&env, index, &upgrade_legacy_swap(&env, index, legacy));
+                    migrated += 1;
+                }
+                Err(_) => complete = false,
+            }
+        }
+
+        if end >= total && complete {
+            env.storage()
+                .instance()
+                .set(&ConfigKey::SchemaVersion, &SCHEMA_VERSION);
+        }
+        Ok(migrated)
+    }
+
+    /// Writes a historical `record` at `index` when migrating from another
+    /// contract (admin only, import mode only).
+    ///
+    /// The record is stored as given, including its swap id and ledger
+    /// sequence, after the usual block, category, app, pair and amount
+    /// checks. Occupied indices are rejected with
+    /// `Error::IndexTaken`, and the swap count becomes `max(count, index + 1)` so skipped indices
+    /// read as `None`. `u64::MAX` leaves no room for the count and is
+    /// rejected with `Error::InvalidRange`. Aggregates are updated while
+    /// aggregation is enabled. A record appended past the count gets a
+    /// cumulative total for `volume_between`; one filling a gap gets none.
+    pub fn import_swap(env: Env, index: u64, record: SwapRecord) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if !Self::is_import_mode(env.clone()) {
+            return Err(Error::ImportDisabled);
+        }
+        if index == u64::MAX {
+            return Err(Error::InvalidRange);
+        }
+        let parent = check_admin_record(&env, &record)?;
+        if read_swap(&env, index).is_some() {
+            return Err(Error::IndexTaken);
+        }
+
+        index_swap(&env, index, &record, parent);
+        let running = add_running_volume(&env, record.amount);
+        let count = Self::get_swap_count(env.clone());
+        if index >= count {
+            let storage = env.storage().persistent();
+            storage.set(&DataKey::CumulativeVolume(index), &running);
+            storage.set(&DataKey::SwapCount, &(index + 1));
+            env.storage()
+                .instance()
+                .set(&ConfigKey::ChainHead, &record_hash(&env, &record));
+        }
+
+        if Self::is_aggregation_enabled(env.clone()) {
+            update_aggregates(&env, &record)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the record at `index` with `new` (admin only), moving its
+    /// contribution to the additive aggregates (volumes, counts, daily and
+    /// per-user, per-asset and per-pair totals) from the old record to the
+    /// new one and re-indexing it. Fees move by the records' stored `fee`,
+    /// which is taken from `new` as given. `new` must pass the same checks
+    /// as an imported record. Emits a `corrected` event.
+    ///
+    /// Last-value aggregates (rates, ticks, candles, rate ranges, the last
+    /// timestamp), the max amount and the cumulative totals stored for
+    /// `volume_between` at `index` and later are not rolled back; use
+    /// `rebuild_aggregates` to recompute them exactly. Records read back
+    /// for a private user carry a placeholder address, so restore the real
+    /// `user` before passing one in.
+    pub fn correct_swap(env: Env, index: u64, new: SwapRecord) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        let old = read_swap(&env, index).ok_or(Error::SwapNotFound)?;
+        let parent = check_admin_record(&env, &new)?;
+
+        unindex_swap(&env, index, &old);
+        index_swap(&env, index, &new, parent);
+        add_running_volume(&env, new.amount - old.amount);
+
+        if Self::is_aggregation_enabled(env.clone()) {
+            apply_counters(&env, &old, true)?;
+            apply_counters(&env, &new, false)?;
+            if new.amount > Self::get_max_swap_amount(env.clone()) {
+                env.storage()
+                    .persistent()
+                    .set(&DataKey::MaxAmount, &new.amount);
+            }
+        }
+
+        publish_event(&env, symbol_short!("corrected"), index);
+        Ok(())
+    }
+
+    /// Folds the per-asset aggregates of `old_asset` (volume, flow counts
+    /// and fees) into `new_asset` and clears them from `old_asset` (admin
+    /// only). `new_asset` keeps its own last rate if it has one.
+    ///
+    /// Raw records keep their original asset code, so per-user, per-pair
+    /// and candle aggregates are left untouched and a later
+    /// `rebuild_aggregates` restores the unmerged totals, listing
+    /// `old_asset` again.
+    pub fn merge_asset(env: Env, old_asset: String, new_asset: String) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if old_asset == new_asset {
+            return Ok(());
+        }
+
+        let storage = env.storage().persistent();
+        for (old_key, new_key) in [
+            (
+                DataKey::AssetVolume(old_asset.clone()),
+                DataKey::AssetVolume(new_asset.clone()),
+            ),
+            (
+                DataKey::AssetFees(old_asset.clone()),
+                DataKey::AssetFees(new_asset.clone()),
+            ),
+        ] {
+            let old: i128 = storage.get(&old_key).unwrap_or(0);
+            let new: i128 = storage.get(&new_key).unwrap_or(0);
+            storage.set(&new_key, &(new + old));
+            storage.remove(&old_key);
+        }
+        for (old_key, new_key) in [
+            (
+                DataKey::AssetOutCount(old_asset.clone()),
+                DataKey::AssetOutCount(new_asset.clone()),
+            ),
+            (
+                DataKey::AssetInCount(old_asset.clone()),
+                DataKey::AssetInCount(new_asset.clone()),
+            ),
+        ] {
+            let old: u64 = storage.get(&old_key).unwrap_or(0);
+            let new: u64 = storage.get(&new_key).unwrap_or(0);
+            storage.set(&new_key, &(new + old));
+            storage.remove(&old_key);
+        }
+
+        let old_rate_key = DataKey::AssetLastRate(old_asset.clone());
+        let new_rate_key = DataKey::AssetLastRate(new_asset.clone());
+        if let Some(rate) = storage.get::<_, i128>(&old_rate_key) {
+            if !storage.has(&new_rate_key) {
+                storage.set(&new_rate_key, &rate);
+            }
+            storage.remove(&old_rate_key);
+        }
+
+        let mut assets = asset_list(&env);
+        if let Some(position) = assets.first_index_of(&old_asset) {
+            assets.remove(position);
+            storage.set(&DataKey::AssetList, &assets);
+        }
+        register_asset(&env, &new_asset);
+        Ok(())
+    }
+
+    /// Returns the Herfindahl index of per-user volumes scaled to
+    /// `0..=10000`: `10000` when one user holds all volume, approaching `0`
+    /// as volume spreads across many users. Returns `0` with no volume.
+    ///
+    /// The sum of squared user volumes is maintained incrementally, so this
+    /// is O(1).
+    pub fn get_trader_concentration(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        let total = Self::get_total_volume(env.clone());
+        if total <= 0 {
+            return 0;
+        }
+        let sum_squares: i128 = read_aggregate(&env, &DataKey::VolumeSumSquares).unwrap_or(0);
+        let total_squared = total.saturating_mul(total);
+
+        // Trade precision for range once the scaled numerator would overflow
+        let index = match sum_squares.checked_mul(10_000) {
+            Some(scaled) => scaled / total_squared,
+            None => sum_squares / (total_squared / 10_000).max(1),
+        };
+        index.clamp(0, 10_000) as u32
+    }
+
+    /// Returns the number of swaps recorded for `user`.
+    pub fn get_user_swap_count(env: Env, user: Address) -> u64 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::UserSwapCount(user)).unwrap_or(0)
+    }
+
+    /// Returns the total amount swapped by `user`.
+    pub fn get_user_volume(env: Env, user: Address) -> i128 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::UserVolume(user)).unwrap_or(0)
+    }
+
+    /// Returns the indices of all of `user`'s swaps in the order they were
+    /// recorded. Empty if the user has no swaps.
+    pub fn get_user_swap_indices(env: Env, user: Address) -> Vec<u64> {
+        ensure_not_frozen(&env);
+        user_swap_indices(&env, &user)
+    }
+
+    /// Hides (`private = true`) or reveals `user`'s address on their swap
+    /// records in read APIs, which then show a placeholder address
+    /// instead. Requires `user`'s authorization. Per-user aggregates and
+    /// queries by address are unaffected.
+    pub fn set_private(env: Env, user: Address, private: bool) {
+        ensure_not_frozen(&env);
+        user.require_auth();
+        let key = DataKey::Private(user);
+        if private {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
+        }
+    }
+
+    /// Returns whether `user` has opted out of appearing in reads.
+    pub fn is_private(env: Env, user: Address) -> bool {
+        ensure_not_frozen(&env);
+        env.storage().persistent().has(&DataKey::Private(user))
+    }
+
+    /// Returns the timestamp of `user`'s first recorded swap, or `None` if
+    /// they have never swapped.
+    pub fn get_user_since(env: Env, user: Address) -> Option<u64> {
+        ensure_not_frozen(&env);
+        env.storage().persistent().get(&DataKey::UserFirstTs(user))
+    }
+
+    /// Returns `user`'s swap at position `recency` counting back from their
+    /// newest (`0`), or `None` if they have fewer swaps.
+    pub fn get_user_swap_at(env: Env, user: Address, recency: u32) -> Option<SwapRecord> {
+        ensure_not_frozen(&env);
+        let indices = user_swap_indices(&env, &user);
+        if recency >= indices.len() {
+            return None;
+        }
+        read_public_swap(&env, indices.get_unchecked(indices.len() - 1 - recency))
+    }
+
+    /// Returns the total amount `user` swapped with a timestamp in
+    /// `[start_ts, end_ts]`.
+    ///
+    /// Walks the user's swaps newest first and stops at the first one older
+    /// than `start_ts`, so it assumes swaps are recorded in time order.
+    pub fn get_user_volume_in_range(env: Env, user: Address, start_ts: u64, end_ts: u64) -> i128 {
+        ensure_not_frozen(&env);
+        let mut volume: i128 = 0;
+
+        for index in user_swap_indices(&env, &user).iter().rev() {
+            let Some(record) = read_swap(&env, index) else {
+                continue;
+            };
+            if record.timestamp < start_ts {
+                break;
+            }
+            if record.timestamp <= end_ts {
+                volume = volume.saturating_add(record.amount);
+            }
+        }
+
+        volume
+    }
+
+    /// Returns up to `limit` of `user`'s swaps where `asset` is on either
+    /// side, newest first.
+    pub fn get_user_swaps_by_asset(
+        env: Env,
+        user: Address,
+        asset: String,
+        limit: u32,
+    ) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let indices = user_swap_indices(&env, &user);
+        let mut swaps = vec![&env];
+
+        for index in indices.iter().rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                if record.from_asset == asset || record.to_asset == asset {
+                    swaps.push_back(record);
+                }
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns up to `limit` swaps recorded under `category`, newest first.
+    pub fn get_swaps_by_category(env: Env, category: Symbol, limit: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let mut swaps = vec![&env];
+
+        for index in (0..total).rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                if record.category == category {
+                    swaps.push_back(record);
+                }
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns up to `limit` of the newest swaps involving `asset` on
+    /// either side, newest first, each paired with `true` when `asset` was
+    /// the `from_asset`.
+    pub fn get_swaps_for_asset(env: Env, asset: String, limit: u32) -> Vec<(SwapRecord, bool)> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let mut swaps = vec![&env];
+
+        for index in (0..total).rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                let sold = record.from_asset == asset;
+                if sold || record.to_asset == asset {
+                    swaps.push_back((record, sold));
+                }
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns up to `limit` of the newest swaps where neither side is
+    /// `asset`, newest first.
+    pub fn get_swaps_excluding_asset(env: Env, asset: String, limit: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let mut swaps = vec![&env];
+
+        for index in (0..total).rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                if record.from_asset != asset && record.to_asset != asset {
+                    swaps.push_back(record);
+                }
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns up to `limit` of the newest swaps whose `amount` lies within
+    /// `[min_amount, max_amount]`, newest first. An inverted band yields an
+    /// empty list.
+    pub fn get_swaps_in_amount_range(
+        env: Env,
+        min_amount: i128,
+        max_amount: i128,
+        limit: u32,
+    ) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let mut swaps = vec![&env];
+        if min_amount > max_amount {
+            return swaps;
+        }
+
+        let total = Self::get_swap_count(env.clone());
+        for index in (0..total).rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                if (min_amount..=max_amount).contains(&record.amount) {
+                    swaps.push_back(record);
+                }
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns up to `limit` of the newest swaps with a timestamp in
+    /// `[now - seconds, now]`, where `now` is the ledger timestamp.
+    pub fn get_swaps_last_seconds(env: Env, seconds: u64, limit: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let now = env.ledger().timestamp();
+        let start = now.saturating_sub(seconds);
+        let total = Self::get_swap_count(env.clone());
+        let mut swaps = vec![&env];
+
+        for index in (0..total).rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                if (start..=now).contains(&record.timestamp) {
+                    swaps.push_back(record);
+                }
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns the `count` swaps with the latest timestamps, newest first.
+    /// Ties keep the newer index first.
+    ///
+    /// Only the newest `count + 50` records are examined, so a record
+    /// recorded that much later than its timestamp suggests may be missed.
+    pub fn get_swaps_time_sorted(env: Env, count: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let tail = (count as u64).saturating_add(TIME_SORT_SLACK);
+        let mut sorted: Vec<SwapRecord> = vec![&env];
+        if count == 0 {
+            return sorted;
+        }
+
+        for index in (total.saturating_sub(tail)..total).rev() {
+            let Some(record) = read_public_swap(&env, index) else {
+                continue;
+            };
+            let position = sorted
+                .iter()
+                .position(|kept| kept.timestamp < record.timestamp)
+                .map_or(sorted.len(), |position| position as u32);
+            if position < count {
+                sorted.insert(position, record);
+                if sorted.len() > count {
+                    sorted.pop_back();
+                }
+            }
+        }
+
+        sorted
+    }
+
+    /// Returns the `n` largest swaps by amount with a timestamp in
+    /// `[start_ts, end_ts]`, largest first. Ties keep recording order. At
+    /// most `n` records are held while scanning.
+    pub fn get_top_swaps_in_range(env: Env, start_ts: u64, end_ts: u64, n: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let mut top: Vec<SwapRecord> = vec![&env];
+        if n == 0 || start_ts > end_ts {
+            return top;
+        }
+
+        let total = Self::get_swap_count(env.clone());
+        for index in 0..total {
+            let Some(record) = read_public_swap(&env, index) else {
+                continue;
+            };
+            if record.timestamp < start_ts || record.timestamp > end_ts {
+                continue;
+            }
+            let position = top
+                .iter()
+                .position(|kept| kept.amount < record.amount)
+                .map_or(top.len(), |position| position as u32);
+            if position < n {
+                top.insert(position, record);
+                if top.len() > n {
+                    top.pop_back();
+                }
+            }
+        }
+
+        top
+    }
+
+    /// Returns the number of swaps recorded under `category`.
+    pub fn get_category_count(env: Env, category: Symbol) -> u64 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::CategoryCount(category)).unwrap_or(0)
+    }
+
+    /// Returns the number of swaps attributed to `app_id`.
+    pub fn get_app_count(env: Env, app_id: Symbol) -> u64 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::AppCount(app_id)).unwrap_or(0)
+    }
+
+    /// Returns the total amount swapped through `app_id`.
+    pub fn get_app_volume(env: Env, app_id: Symbol) -> i128 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::AppVolume(app_id)).unwrap_or(0)
+    }
+
+    /// Returns the amount of `asset` sold by `user` across their swaps.
+    pub fn get_user_asset_volume(env: Env, user: Address, asset: String) -> i128 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::UserAssetVolume(user, asset)).unwrap_or(0)
+    }
+
+    /// Returns the newest `limit` swaps made by any of `users`, merged in
+    /// global newest-first order. Duplicate addresses are ignored.
+    pub fn get_swaps_for_users(env: Env, users: Vec<Address>, limit: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        // Per-user index lists and how many entries remain unread in each
+        let mut lists: Vec<Vec<u64>> = vec![&env];
+        let mut remaining: Vec<u32> = vec![&env];
+        let mut seen: Vec<Address> = vec![&env];
+        for user in users.iter() {
+            if seen.contains(&user) {
+                continue;
+            }
+            let indices = user_swap_indices(&env, &user);
+            remaining.push_back(indices.len());
+            lists.push_back(indices);
+            seen.push_back(user);
+        }
+
+        let mut swaps = vec![&env];
+        while swaps.len() < limit {
+            // Pick the list whose next unread index is the highest
+            let mut best: Option<(u32, u64)> = None;
+            for i in 0..lists.len() {
+                let left = remaining.get_unchecked(i);
+                if left == 0 {
+                    continue;
+                }
+                let index = lists.get_unchecked(i).get_unchecked(left - 1);
+                if best.map_or(true, |(_, top)| index > top) {
+                    best = Some((i, index));
+                }
+            }
+
+            let Some((list, index)) = best else {
+                break;
+            };
+            remaining.set(list, remaining.get_unchecked(list) - 1);
+            if let Some(record) = read_public_swap(&env, index) {
+                swaps.push_back(record);
+            }
+        }
+
+        swaps
+    }
+
+    /// Records several swaps in one call, returning their indices in order.
+    ///
+    /// Each entry goes through the same checks as `record_swap`; if any
+    /// entry fails, the whole batch is rejected. Batches larger than the
+    /// configured maximum are rejected with `Error::BatchTooLarge` before
+    /// any entry is processed.
+    pub fn record_swaps_batch(env: Env, swaps: Vec<SwapInput>) -> Result<Vec<u64>, Error> {
+        ensure_not_frozen(&env);
+        if swaps.len() > Self::get_max_batch_size(env.clone()) {
+            return Err(Error::BatchTooLarge);
+        }
+
+        let mut indices = vec![&env];
+        for input in swaps.iter() {
+            indices.push_back(store_swap(&env, input)?);
+        }
+        Ok(indices)
+    }
+
+    /// Caps how many records are kept (admin only). Once more are stored,
+    /// each new swap rolls the oldest live one off. Aggregates keep its
+    /// contribution, except the per-status counts, which only cover live
+    /// records; a later `rebuild_aggregates` sees only live records and so
+    /// drops the rolled-off history from every aggregate. After lowering
+    /// the cap, the excess is trimmed a few records per swap. `0` keeps
+    /// every record.
+    pub fn set_max_records(env: Env, max: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage().instance().set(&ConfigKey::MaxRecords, &max);
+        Ok(())
+    }
+
+    /// Returns the record cap (`0` when unlimited).
+    pub fn get_max_records(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::MaxRecords)
+            .unwrap_or(0)
+    }
+
+    /// Returns how many records have been rolled off under the record cap.
+    /// Records below this index are no longer stored.
+    pub fn get_dropped_count(env: Env) -> u64 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .persistent()
+            .get(&DataKey::DroppedCount)
+            .unwrap_or(0)
+    }
+
+    /// Sets the maximum number of swaps accepted per batch (admin only).
+    pub fn set_max_batch_size(env: Env, size: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        env.storage()
+            .instance()
+            .set(&ConfigKey::MaxBatchSize, &size);
+        Ok(())
+    }
+
+    /// Returns the maximum batch size. Defaults to 50.
+    pub fn get_max_batch_size(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::MaxBatchSize)
+            .unwrap_or(DEFAULT_MAX_BATCH_SIZE)
+    }
+
+    /// Sets the fee, in basis points of the sold amount, accrued to each
+    /// swap's `from_asset` (admin only). Must not exceed 10_000. Swaps
+    /// already recorded keep the fee stored on them.
+    pub fn set_fee_bps(env: Env, bps: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if bps > 10_000 {
+            return Err(Error::InvalidFee);
+        }
+        env.storage().instance().set(&ConfigKey::FeeBps, &bps);
+        Ok(())
+    }
+
+    /// Returns the fee rate in basis points. Defaults to `0`.
+    pub fn get_fee_bps(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .instance()
+            .get(&ConfigKey::FeeBps)
+            .unwrap_or(0)
+    }
+
+    /// Sets the price of one unit of `asset` in the common quote currency,
+    /// scaled by the rate scale (admin only). Returns `Error::InvalidPrice`
+    /// for a negative price.
+    pub fn set_asset_price(env: Env, asset: String, price: i128) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if price < 0 {
+            return Err(Error::InvalidPrice);
+        }
+        env.storage()
+            .persistent()
+            .set(&DataKey::AssetPrice(asset), &price);
+        Ok(())
+    }
+
+    /// Returns the configured price of `asset`, if any.
+    pub fn get_asset_price(env: Env, asset: String) -> Option<i128> {
+        ensure_not_frozen(&env);
+        env.storage().persistent().get(&DataKey::AssetPrice(asset))
+    }
+
+    /// Sets how many decimals `asset`'s raw amounts carry (admin only).
+    /// Returns `Error::InvalidDecimals` above 18.
+    pub fn set_asset_decimals(env: Env, asset: String, decimals: u32) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if decimals > MAX_ASSET_DECIMALS {
+            return Err(Error::InvalidDecimals);
+        }
+        env.storage()
+            .persistent()
+            .set(&DataKey::AssetDecimals(asset), &decimals);
+        Ok(())
+    }
+
+    /// Returns the configured decimals of `asset`. Defaults to 7.
+    pub fn get_asset_decimals(env: Env, asset: String) -> u32 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .persistent()
+            .get(&DataKey::AssetDecimals(asset))
+            .unwrap_or(DEFAULT_ASSET_DECIMALS)
+    }
+
+    /// Sets the decimals and price of several assets at once (admin only),
+    /// each entry being `(asset, decimals, price)`. Entries are validated
+    /// as in `set_asset_decimals` and `set_asset_price`, and any invalid
+    /// entry rejects the whole call. Lists longer than the maximum batch
+    /// size are rejected with `Error::BatchTooLarge`.
+    pub fn configure_assets(env: Env, configs: Vec<(String, u32, i128)>) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if configs.len() > Self::get_max_batch_size(env.clone()) {
+            return Err(Error::BatchTooLarge);
+        }
+
+        let storage = env.storage().persistent();
+        for (asset, decimals, price) in configs.iter() {
+            if decimals > MAX_ASSET_DECIMALS {
+                return Err(Error::InvalidDecimals);
+            }
+            if price < 0 {
+                return Err(Error::InvalidPrice);
+            }
+            storage.set(&DataKey::AssetDecimals(asset.clone()), &decimals);
+            storage.set(&DataKey::AssetPrice(asset), &price);
+        }
+        Ok(())
+    }
+
+    /// Splits a raw amount of `asset` into `(whole, fractional)` parts
+    /// using the asset's decimals, e.g. `1_500_000` at 6 decimals is
+    /// `(1, 500000)`. Both parts carry the sign of `raw`.
+    pub fn display_amount(env: Env, asset: String, raw: i128) -> (i128, i128) {
+        ensure_not_frozen(&env);
+        let decimals = Self::get_asset_decimals(env, asset);
+        let scale = 10_i128.pow(decimals);
+        (raw / scale, raw % scale)
+    }
+
+    /// Sets the circulating supply of `asset`, in raw units, used by
+    /// `get_turnover` (admin only). Returns `Error::InvalidSupply` for a
+    /// negative supply.
+    pub fn set_asset_supply(env: Env, asset: String, supply: i128) -> Result<(), Error> {
+        ensure_not_frozen(&env);
+        require_admin(&env)?;
+        if supply < 0 {
+            return Err(Error::InvalidSupply);
+        }
+        env.storage()
+            .persistent()
+            .set(&DataKey::AssetSupply(asset), &supply);
+        Ok(())
+    }
+
+    /// Returns the configured circulating supply of `asset`, or `0` if
+    /// none is set.
+    pub fn get_asset_supply(env: Env, asset: String) -> i128 {
+        ensure_not_frozen(&env);
+        env.storage()
+            .persistent()
+            .get(&DataKey::AssetSupply(asset))
+            .unwrap_or(0)
+    }
+
+    /// Returns the turnover of `asset`: its traded volume, as the sold
+    /// asset, relative to its circulating supply, scaled by the rate
+    /// scale. Returns `0` while no supply is configured.
+    pub fn get_turnover(env: Env, asset: String) -> i128 {
+        ensure_not_frozen(&env);
+        let supply = Self::get_asset_supply(env.clone(), asset.clone());
+        if supply == 0 {
+            return 0;
+        }
+        let volume: i128 = read_aggregate(&env, &DataKey::AssetVolume(asset)).unwrap_or(0);
+        let scale = Self::get_rate_scale(env);
+
+        // Trade precision for range once the scaled volume would overflow
+        match volume.checked_mul(scale) {
+            Some(scaled) => scaled / supply,
+            None => (volume / supply).saturating_mul(scale),
+        }
+    }
+
+    /// Returns the average rate over the last `n` swaps of the pair,
+    /// scaled by the rate scale, or `None` if the pair has no priced swaps.
+    pub fn get_recent_avg_rate(
+        env: Env,
+        from_asset: String,
+        to_asset: String,
+        n: u32,
+    ) -> Option<i128> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let scale = Self::get_rate_scale(env.clone());
+        let mode = Self::get_rounding_mode(env.clone());
+        let mut sum: i128 = 0;
+        let mut matched: u32 = 0;
+
+        for index in (0..total).rev() {
+            if matched >= n {
+                break;
+            }
+            if let Some(record) = read_swap(&env, index) {
+                if record.from_asset == from_asset && record.to_asset == to_asset {
+                    if let Some(rate) = compute_rate(scale, mode, record.amount, record.to_amount) {
+                        sum += rate;
+                        matched += 1;
+                    }
+                }
+            }
+        }
+
+        if matched == 0 {
+            None
+        } else {
+            Some(div_round(sum, matched as i128, mode))
+        }
+    }
+
+    /// Returns the number of swaps in each day of the inclusive range
+    /// `[start_day, end_day]`, where a day index is `timestamp / 86400`.
+    ///
+    /// At most 366 days are returned; an inverted range yields an empty
+    /// series.
+    pub fn get_daily_count_series(env: Env, start_day: u64, end_day: u64) -> Vec<u32> {
+        ensure_not_frozen(&env);
+        let mut series = vec![&env];
+        if start_day > end_day {
+            return series;
+        }
+
+        let end_day = end_day.min(start_day.saturating_add(MAX_SERIES_DAYS - 1));
+        for day in start_day..=end_day {
+            series.push_back(daily_count(&env, day));
+        }
+        series
+    }
+
+    /// Returns the day index with the highest swap volume and that volume,
+    /// or `None` if nothing has been recorded. Ties keep the earlier day to
+    /// reach the volume. Like the max amount, corrections do not lower it.
+    pub fn get_busiest_day(env: Env) -> Option<(u64, i128)> {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DayKey::Busiest)
+    }
+
+    /// Returns the indices of every day with at least one swap, ascending.
+    pub fn get_active_days(env: Env) -> Vec<u64> {
+        ensure_not_frozen(&env);
+        active_days(&env)
+    }
+
+    /// Returns how many distinct days have at least one swap, without
+    /// loading the full list from `get_active_days`.
+    pub fn get_active_day_count(env: Env) -> u32 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DayKey::ActiveCount).unwrap_or(0)
+    }
+
+    /// Returns the swap volume of each of the 24 hours of `day_index`,
+    /// starting at 00:00 UTC. Hours without swaps are `0`, as is every
+    /// hour of a day too far out to have an hour index.
+    pub fn get_intraday_volume(env: Env, day_index: u64) -> Vec<i128> {
+        ensure_not_frozen(&env);
+        let hours = SECONDS_PER_DAY / SECONDS_PER_HOUR;
+        let first_hour = day_index.checked_mul(hours);
+        let mut volumes = vec![&env];
+        for offset in 0..hours {
+            let volume = first_hour
+                .and_then(|first| first.checked_add(offset))
+                .and_then(|hour| read_aggregate(&env, &DayKey::HourVolume(hour)));
+            volumes.push_back(volume.unwrap_or(0));
+        }
+        volumes
+    }
+
+    /// Projects the full-day volume for `day_index` from its volume so far.
+    ///
+    /// During the current ledger day the accumulated volume is scaled by
+    /// `86400 / elapsed_seconds`; in the day's first second the volume is
+    /// returned unscaled. Any other day returns its recorded volume as is.
+    pub fn get_day_run_rate_projection(env: Env, day_index: u64) -> i128 {
+        ensure_not_frozen(&env);
+        let volume = daily_volume(&env, day_index);
+        let now = env.ledger().timestamp();
+        if day_index != now / SECONDS_PER_DAY {
+            return volume;
+        }
+
+        let elapsed = now % SECONDS_PER_DAY;
+        if elapsed == 0 {
+            return volume;
+        }
+        volume.saturating_mul(SECONDS_PER_DAY as i128) / elapsed as i128
+    }
+
+    /// Returns the number of swaps in each power-of-ten amount bucket, where
+    /// entry `N` counts amounts in `[10^N, 10^(N+1))`.
+    pub fn get_bucket_counts(env: Env) -> Vec<u64> {
+        ensure_not_frozen(&env);
+        amount_histogram(&env)
+    }
+
+    /// Returns up to `limit` of the newest swaps whose amount falls in
+    /// bucket `[10^bucket, 10^(bucket+1))`.
+    pub fn get_swaps_in_bucket(env: Env, bucket: u32, limit: u32) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let total = Self::get_swap_count(env.clone());
+        let mut swaps = vec![&env];
+
+        for index in (0..total).rev() {
+            if swaps.len() >= limit {
+                break;
+            }
+            if let Some(record) = read_public_swap(&env, index) {
+                if amount_bucket(record.amount) == bucket {
+                    swaps.push_back(record);
+                }
+            }
+        }
+
+        swaps
+    }
+
+    /// Returns an approximate median swap amount.
+    ///
+    /// Amounts are counted in power-of-ten buckets (`[10^N, 10^(N+1))`) as
+    /// they are recorded, and the midpoint of the bucket holding the median
+    /// is returned. The result is only accurate to within its bucket, but
+    /// is robust against outliers. Returns `0` when no swaps are recorded.
+    pub fn get_approx_median_amount(env: Env) -> i128 {
+        ensure_not_frozen(&env);
+        Self::get_amount_percentile(env, 50)
+    }
+
+    /// Returns the approximate swap amount at `percentile` (clamped to
+    /// `0..=100`), i.e. the midpoint of the power-of-ten bucket holding
+    /// that share of recorded swaps. Like the median, it is only accurate
+    /// to within its bucket. Returns `0` when no swaps are recorded.
+    pub fn get_amount_percentile(env: Env, percentile: u32) -> i128 {
+        ensure_not_frozen(&env);
+        let histogram = amount_histogram(&env);
+        let total: u64 = histogram.iter().sum();
+        if total == 0 {
+            return 0;
+        }
+
+        let percentile = percentile.min(100) as u64;
+        let target = (total * percentile).div_ceil(100).max(1);
+        let mut seen: u64 = 0;
+        for (bucket, count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return bucket_midpoint(bucket as u32);
+            }
+        }
+        0
+    }
+
+    /// Returns the `(open, high, low, close)` rates for the pair on
+    /// `day_index` (days since the Unix epoch), scaled by the rate scale.
+    /// Open is the
+    /// day's first rate and close its latest.
+    pub fn get_candle(
+        env: Env,
+        from: String,
+        to: String,
+        day_index: u64,
+    ) -> Option<(i128, i128, i128, i128)> {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &PairKey::Candle(from, to, day_index))
+    }
+
+    /// Returns the `(min, max)` rates the pair has traded at, scaled by the
+    /// rate scale, or `None` if it has no priced swaps.
+    pub fn get_rate_spread(env: Env, from: String, to: String) -> Option<(i128, i128)> {
+        ensure_not_frozen(&env);
+        let min = read_aggregate(&env, &PairKey::RateMin(from.clone(), to.clone()))?;
+        let max = read_aggregate(&env, &PairKey::RateMax(from, to))?;
+        Some((min, max))
+    }
+
+    /// Returns the total amount sold through the `from` -> `to` pair.
+    pub fn get_pair_volume(env: Env, from: String, to: String) -> i128 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &PairKey::Volume(from, to)).unwrap_or(0)
+    }
+
+    /// Returns the volume, swap count and latest rate (scaled by the rate
+    /// scale) of the `from` -> `to` pair. All zero for an untraded pair.
+    pub fn get_pair_stats(env: Env, from: String, to: String) -> PairStats {
+        ensure_not_frozen(&env);
+        PairStats {
+            volume: Self::get_pair_volume(env.clone(), from.clone(), to.clone()),
+            count: read_aggregate(&env, &PairKey::Count(from.clone(), to.clone())).unwrap_or(0),
+            last_rate: read_aggregate(&env, &PairKey::LastRate(from.clone(), to.clone()))
+                .unwrap_or(0),
+            from_asset: from,
+            to_asset: to,
+        }
+    }
+
+    /// Returns whether any swap selling `from` for `to` is counted in the
+    /// per-pair aggregates.
+    pub fn pair_exists(env: Env, from: String, to: String) -> bool {
+        ensure_not_frozen(&env);
+        read_aggregate::<_, u64>(&env, &PairKey::Count(from, to)).unwrap_or(0) > 0
+    }
+
+    /// Returns whether the pair's latest swap moved its rate up, down or
+    /// not at all. Pairs with fewer than two priced swaps are `Flat`.
+    pub fn get_last_tick_direction(env: Env, from: String, to: String) -> TickDirection {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &PairKey::Tick(from, to)).unwrap_or(TickDirection::Flat)
+    }
+
+    /// Returns every distinct `(from, to)` pair traded, in order of first
+    /// appearance.
+    pub fn get_pair_list(env: Env) -> Vec<(String, String)> {
+        ensure_not_frozen(&env);
+        pair_list(&env)
+    }
+
+    /// Returns every asset that has appeared on either side of a swap, in
+    /// order of first appearance.
+    pub fn get_asset_list(env: Env) -> Vec<String> {
+        ensure_not_frozen(&env);
+        asset_list(&env)
+    }
+
+    /// Returns up to `limit` assets from the asset list starting at
+    /// `offset`. An offset past the end yields an empty list.
+    pub fn get_assets_paginated(env: Env, offset: u32, limit: u32) -> Vec<String> {
+        ensure_not_frozen(&env);
+        let assets = asset_list(&env);
+        if offset >= assets.len() {
+            return vec![&env];
+        }
+        let end = offset.saturating_add(limit).min(assets.len());
+        assets.slice(offset..end)
+    }
+
+    /// Returns the fees accrued in `asset`.
+    pub fn get_asset_fees(env: Env, asset: String) -> i128 {
+        ensure_not_frozen(&env);
+        read_aggregate(&env, &DataKey::AssetFees(asset)).unwrap_or(0)
+    }
+
+    /// Returns the value of all accrued fees in the quote currency.
+    ///
+    /// Each asset's fee total is converted with its configured price
+    /// (`fees * price / scale`); assets without a configured price
+    /// contribute nothing, so the result is only as complete as the
+    /// price configuration. Saturates at `i128::MAX` rather than
+    /// overflowing.
+    pub fn get_total_fee_value(env: Env) -> i128 {
+        ensure_not_frozen(&env);
+        let scale = Self::get_rate_scale(env.clone());
+        let mut total: i128 = 0;
+        for asset in asset_list(&env).iter() {
+            if let Some(price) = Self::get_asset_price(env.clone(), asset.clone()) {
+                let value = Self::get_asset_fees(env.clone(), asset)
+                    .checked_mul(price)
+                    .map_or(i128::MAX, |value| value / scale);
+                total = total.saturating_add(value);
+            }
+        }
+        total
+    }
+
+    /// Returns `(out_count, in_count)` for `asset`: how many swaps sold it
+    /// (`from_asset`) and how many bought it (`to_asset`).
+    pub fn get_asset_flow_counts(env: Env, asset: String) -> (u64, u64) {
+        ensure_not_frozen(&env);
+        let out_count = read_aggregate(&env, &DataKey::AssetOutCount(asset.clone())).unwrap_or(0);
+        let in_count = read_aggregate(&env, &DataKey::AssetInCount(asset)).unwrap_or(0);
+        (out_count, in_count)
+    }
+
+    /// Returns one summary row per known asset, in registry order.
+    ///
+    /// `volume` is the amount of the asset sold, `count` the swaps involving
+    /// it on either side and `last_rate` the rate (scaled by the rate scale)
+    /// of the latest swap selling it, or `0` if it has never been sold.
+    pub fn get_asset_summaries(env: Env) -> Vec<AssetSummary> {
+        ensure_not_frozen(&env);
+        let mut summaries = vec![&env];
+        for asset in asset_list(&env).iter() {
+            let (out_count, in_count) = Self::get_asset_flow_counts(env.clone(), asset.clone());
+            summaries.push_back(AssetSummary {
+                volume: read_aggregate(&env, &DataKey::AssetVolume(asset.clone())).unwrap_or(0),
+                count: out_count + in_count,
+                last_rate: read_aggregate(&env, &DataKey::AssetLastRate(asset.clone()))
+                    .unwrap_or(0),
+                asset,
+            });
+        }
+        summaries
+    }
+
+    /// Returns how many consecutive days, ending with the current ledger
+    /// day, `user` has recorded at least one swap on. The streak is `0` if
+    /// the user has not swapped today and stops at the first missed day.
+    pub fn get_user_current_streak(env: Env, user: Address) -> u32 {
+        ensure_not_frozen(&env);
+        let mut expected = env.ledger().timestamp() / SECONDS_PER_DAY;
+        let mut streak: u32 = 0;
+
+        for index in user_swap_indices(&env, &user).iter().rev() {
+            let Some(record) = read_swap(&env, index) else {
+                continue;
+            };
+            let day = record.timestamp / SECONDS_PER_DAY;
+            if day > expected {
+                // Already counted this day (or a future-dated swap)
+                continue;
+            }
+            if day < expected {
+                break;
+            }
+            streak += 1;
+            if expected == 0 {
+                break;
+            }
+            expected -= 1;
+        }
+
+        streak
+    }
+
+    /// Returns the partial fills recorded under `parent_id`, ordered by
+    /// timestamp (oldest first).
+    pub fn get_swaps_by_parent(env: Env, parent_id: BytesN<32>) -> Vec<SwapRecord> {
+        ensure_not_frozen(&env);
+        let fills: Vec<u64> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::ParentSwaps(parent_id))
+            .unwrap_or(vec![&env]);
+
+        // Insertion sort by timestamp; fills are usually already in order
+        let mut swaps: Vec<SwapRecord> = vec![&env];
+        for index in fills.iter() {
+            let Some(record) = read_public_swap(&env, index) else {
+                continue;
+            };
+            let mut pos = swaps.len();
+            while pos > 0 && swaps.get_unchecked(pos - 1).timestamp > record.timestamp {
+                pos -= 1;
+            }
+            swaps.insert(pos, record);
+        }
+        swaps
+    }
+
+    /// Returns a 0-100 reputation score for `user`: the share of their
+    /// settled swaps that completed rather than failed. Users without
+    /// settled swaps get a neutral `50`.
+    pub fn get_user_reputation(env: Env, user: Address) -> u32 {
+        ensure_not_frozen(&env);
+        let (completed, failed) = user_outcomes(&env, &user);
+        let settled = completed as u64 + failed as u64;
+        if settled == 0 {
+            return 50;
+        }
+        (completed as u64 * 100 / settled) as u32
+    }
+
+    /// Returns the swap count, volume and latest swap timestamp for `user`
+    /// in a single call. All fields are zero for users with no swaps.
+    pub fn get_user_stats(env: Env, user: Address) -> UserStats {
+        ensure_not_frozen(&env);
+        let last_timestamp = read_aggregate(&env, &DataKey::UserLastTs(user.clone())).unwrap_or(0);
+
+        UserStats {
+            count: Self::get_user_swap_count(env.clone(), user.clone()),
+            volume: Self::get_user_volume(env, user),
+            last_timestamp,
+        }
+    }
+}
+
+/// Validates and stores a single swap, updating indices, aggregates and
+/// events. Shared by `record_swap` and `record_swaps_batch`.
+fn store_swap(env: &Env, input: SwapInput) -> Result<u64, Error> {
+    let SwapInput {
+        user,
+        from_asset,
+        to_asset,
+        amount,
+        to_amount,
+        timestamp,
+        category,
+        parent_id,
+        app_id,
+        deadline_ts,
+    } = input;
+
+    if SwapTrackerContract::is_paused(env.clone()) {
+        return Err(Error::Paused);
+    }
+    if SwapTrackerContract::is_blocked(env.clone(), user.clone()) {
+        return Err(Error::AddressBlocked);
+    }
+    if !SwapTrackerContract::is_category_allowed(env.clone(), category.clone()) {
+        return Err(Error::CategoryNotAllowed);
+    }
+    if !SwapTrackerContract::is_app_allowed(env.clone(), app_id.clone()) {
+        return Err(Error::AppNotAllowed);
+    }
+    if !pair_permitted(env, &from_asset, &to_asset) {
+        return Err(Error::PairNotApproved);
+    }
+    if amount < SwapTrackerContract::get_asset_min(env.clone(), from_asset.clone()) {
+        return Err(Error::AmountTooSmall);
+    }
+    if amount > MAX_SWAP_AMOUNT {
+        return Err(Error::AmountTooLarge);
+    }
+    let parent = parent_key(&parent_id)?;
+    if SwapTrackerContract::is_monotonic_ts_required(env.clone()) {
+        let last: Option<u64> = read_aggregate(env, &DataKey::UserLastTs(user.clone()));
+        if last == Some(timestamp) {
+            return Err(Error::TimestampCollision);
+        }
+    }
+
+    // Reject identical swaps from the same user within the window,
+    // which usually indicate a client retry
+    let now = env.ledger().timestamp();
+    let window = SwapTrackerContract::get_duplicate_window(env.clone());
+    let fingerprint = swap_fingerprint(env, &from_asset, &to_asset, amount);
+    if window > 0 {
+        let last: Option<(BytesN<32>, u64)> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::UserLastSwap(user.clone()));
+        if let Some((last_hash, last_ts)) = last {
+            if last_hash == fingerprint && now < last_ts.saturating_add(window) {
+                return Err(Error::DuplicateTooSoon);
+            }
+        }
+    }
+
+    // Per-ledger counters live in temporary storage and simply expire
+    let per_ledger = SwapTrackerContract::get_max_swaps_per_ledger(env.clone());
+    if per_ledger > 0 {
+        let key = DataKey::LedgerSwapCount(env.ledger().sequence());
+        let accepted: u32 = env.storage().temporary().get(&key).unwrap_or(0);
+        if accepted >= per_ledger {
+            return Err(Error::LedgerThrottled);
+        }
+        env.storage().temporary().set(&key, &(accepted + 1));
+    }
+
+    let interval = SwapTrackerContract::get_min_swap_interval(env.clone());
+    if SwapTrackerContract::next_allowed_swap(env.clone(), user.clone()) > 0 {
+        return Err(Error::RateLimited);
+    }
+
+    // Reject exact replays while their idempotency key is still live. The
+    // pre-filter skips the exact lookup for keys that were never seen.
+    let ttl = SwapTrackerContract::get_idempotency_ttl(env.clone());
+    if ttl > 0 {
+        let hash = idempotency_key(env, &user, &from_asset, &to_asset, amount, timestamp);
+        let maybe_seen = dup_filter_check_and_set(env, &hash);
+        let key = DataKey::SeenHash(hash);
+        let temporary = env.storage().temporary();
+        if maybe_seen && temporary.has(&key) {
+            return Err(Error::DuplicateSwap);
+        }
+        let ttl = ttl.min(env.storage().max_ttl());
+        temporary.set(&key, &true);
+        temporary.extend_ttl(&key, ttl, ttl);
+    }
+
+    // Get current swap count, defaulting to 0
+    let count: u64 = env
+        .storage()
+        .persistent()
+        .get(&DataKey::SwapCount)
+        .unwrap_or(0);
+
+    let aggregate = SwapTrackerContract::is_aggregation_enabled(env.clone());
+    let average = if aggregate && count > 0 {
+        SwapTrackerContract::get_total_volume(env.clone()) / count as i128
+    } else {
+        0
+    };
+    let threshold =
+        average.checked_mul(SwapTrackerContract::get_circuit_multiplier(env.clone()) as i128);
+    let trip = matches!(threshold, Some(limit) if limit > 0 && amount > limit);
+
+    let quote_value = match SwapTrackerContract::get_asset_price(env.clone(), from_asset.clone()) {
+        Some(price) => {
+            amount.checked_mul(price).ok_or(Error::Overflow)?
+                / SwapTrackerContract::get_rate_scale(env.clone())
+        }
+        None => 0,
+    };
+
+    let swap_id = compute_swap_id(env, &user, &from_asset, &to_asset, amount, timestamp, count);
+    let record = SwapRecord {
+        user: user.clone(),
+        from_asset: from_asset.clone(),
+        to_asset: to_asset.clone(),
+        amount,
+        to_amount,
+        timestamp,
+        category,
+        status: if SwapTrackerContract::get_confirmation_threshold(env.clone()) > 0 {
+            SwapStatus::Pending
+        } else {
+            SwapStatus::Completed
+        },
+        confirmations: 0,
+        swap_id: swap_id.clone(),
+        ledger_seq: env.ledger().sequence(),
+        parent_id: parent_id.clone(),
+        app_id,
+        deadline_ts,
+        quote_value,
+        fee: amount
+            .checked_mul(SwapTrackerContract::get_fee_bps(env.clone()) as i128)
+            .ok_or(Error::Overflow)?
+            / 10_000,
+        prev_hash: chain_head(env),
+    };
+    env.storage()
+        .instance()
+        .set(&ConfigKey::ChainHead, &record_hash(env, &record));
+
+    // Records may have rolled off, so judge by the user's history instead
+    // of their live index
+    let first_swap = SwapTrackerContract::get_user_swap_count(env.clone(), user.clone()) == 0
+        && SwapTrackerContract::get_user_since(env.clone(), user.clone()).is_none();
+    index_swap(env, count, &record, parent);
+
+    let running = add_running_volume(env, amount);
+    env.storage()
+        .persistent()
+        .set(&DataKey::CumulativeVolume(count), &running);
+
+    // Increment and store the new count
+    let new_count = count + 1;
+    env.storage()
+        .persistent()
+        .set(&DataKey::SwapCount, &new_count);
+    drop_excess_records(env, new_count);
+
+    if window > 0 || interval > 0 {
+        env.storage()
+            .persistent()
+            .set(&DataKey::UserLastSwap(user.clone()), &(fingerprint, now));
+    }
+
+    let tick = if aggregate {
+        update_aggregates(env, &record)?
+    } else {
+        // The timestamp collision check reads this, so keep it current
+        // while the other aggregates go stale
+        env.storage()
+            .persistent()
+            .set(&DataKey::UserLastTs(user.clone()), &timestamp);
+        TickDirection::Flat
+    };
+
+    // Emit a contract event for real-time listeners, with a shard topic
+    // appended after the version so indexers can split the stream
+    let shards = SwapTrackerContract::get_event_shards(env.clone());
+    let shard = (count % shards as u64) as u32;
+    let rate = compute_rate(
+        SwapTrackerContract::get_rate_scale(env.clone()),
+        SwapTrackerContract::get_rounding_mode(env.clone()),
+        amount,
+        to_amount,
+    )
+    .filter(|_| to_amount > 0);
+    env.events().publish(
+        (symbol_short!("swap"), EVENT_VERSION, shard),
+        (
+            user,
+            from_asset,
+            to_asset,
+            amount,
+            timestamp,
+            record.ledger_seq,
+            tick,
+            rate.unwrap_or(0),
+            rate.is_none(),
+        ),
+    );
+
+    let interval = SwapTrackerContract::get_milestone_interval(env.clone());
+    if interval > 0 && new_count % interval == 0 {
+        publish_event(env, symbol_short!("milestone"), new_count);
+    }
+    if aggregate {
+        check_user_milestone(env, &record.user);
+    }
+    if first_swap {
+        publish_event(env, Symbol::new(env, "new_trader"), record.user.clone());
+    }
+    notify_hook(env, count, &record);
+
+    if trip {
+        env.storage().instance().set(&ConfigKey::Paused, &true);
+        publish_event(
+            env,
+            Symbol::new(env, "circuit_break"),
+            (count, amount, average),
+        );
+    }
+
+    Ok(count)
+}
+
+/// Rolls the oldest live records off once more than the configured cap
+/// are stored, counting each in `DataKey::DroppedCount`. Indices below the
+/// dropped count are always gone, so it doubles as the oldest live index.
+fn drop_excess_records(env: &Env, count: u64) {
+    let cap = SwapTrackerContract::get_max_records(env.clone()) as u64;
+    if cap == 0 {
+        return;
+    }
+
+    let storage = env.storage().persistent();
+    let dropped = SwapTrackerContract::get_dropped_count(env.clone());
+    let excess = count.saturating_sub(dropped).saturating_sub(cap);
+    let drops = excess.min(MAX_DROPS_PER_SWAP);
+    for index in dropped..dropped + drops {
+        if let Some(record) = read_swap(env, index) {
+            unindex_swap(env, index, &record);
+            storage.remove(&DataKey::Swap(index));
+            storage.remove(&DataKey::SwapFlag(index));
+            storage.remove(&DataKey::CumulativeVolume(index));
+        }
+    }
+    if drops > 0 {
+        storage.set(&DataKey::DroppedCount, &(dropped + drops));
+    }
+}
+
+/// Calls `on_swap(record)` on the configured hook contract, if any. A
+/// failing hook is reported with a `hook_failed` event carrying the swap
+/// index instead of aborting the swap.
+fn notify_hook(env: &Env, index: u64, record: &SwapRecord) {
+    let Some(hook) = SwapTrackerContract::get_hook_contract(env.clone()) else {
+        return;
+    };
+    let result = env.try_invoke_contract::<Val, soroban_sdk::Error>(
+        &hook,
+        &Symbol::new(env, "on_swap"),
+        vec![env, record.clone().into_val(env)],
+    );
+    if result.is_err() {
+        publish_event(env, Symbol::new(env, "hook_failed"), index);
+    }
+}
+
+/// Applies the block, category, app, pair and amount checks of
+/// `record_swap` to a record written by the admin, returning its parent
+/// key.
+fn check_admin_record(env: &Env, record: &SwapRecord) -> Result<Option<BytesN<32>>, Error> {
+    if SwapTrackerContract::is_blocked(env.clone(), record.user.clone()) {
+        return Err(Error::AddressBlocked);
+    }
+    if !SwapTrackerContract::is_category_allowed(env.clone(), record.category.clone()) {
+        return Err(Error::CategoryNotAllowed);
+    }
+    if !SwapTrackerContract::is_app_allowed(env.clone(), record.app_id.clone()) {
+        return Err(Error::AppNotAllowed);
+    }
+    if !pair_permitted(env, &record.from_asset, &record.to_asset) {
+        return Err(Error::PairNotApproved);
+    }
+    if record.amount < SwapTrackerContract::get_asset_min(env.clone(), record.from_asset.clone()) {
+        return Err(Error::AmountTooSmall);
+    }
+    if record.amount > MAX_SWAP_AMOUNT {
+        return Err(Error::AmountTooLarge);
+    }
+    parent_key(&record.parent_id)
+}
+
+/// Parses an optional parent id into its 32-byte key.
+fn parent_key(parent_id: &Option<Bytes>) -> Result<Option<BytesN<32>>, Error> {
+    match parent_id {
+        Some(id) => BytesN::<32>::try_from(id)
+            .map(Some)
+            .map_err(|_| Error::InvalidParentId),
+        None => Ok(None),
+    }
+}
+
+/// Stores `record` at `index` together with its id lookup, user, parent
+/// and asset indices. Index lists stay sorted even when records are
+/// written out of order.
+fn index_swap(env: &Env, index: u64, record: &SwapRecord, parent: Option<BytesN<32>>) {
+    let storage = env.storage().persistent();
+    write_swap(env, index, record);
+    storage.set(&DataKey::SwapById(record.swap_id.clone()), &index);
+
+    let first_key = DataKey::UserFirstTs(record.user.clone());
+    let first_ts: Option<u64> = storage.get(&first_key);
+    if first_ts.map_or(true, |first| record.timestamp < first) {
+        storage.set(&first_key, &record.timestamp);
+    }
+    let mut user_swaps = user_swap_indices(env, &record.user);
+    insert_sorted(&mut user_swaps, index);
+    storage.set(&DataKey::UserSwaps(record.user.clone()), &user_swaps);
+
+    register_asset(env, &record.from_asset);
+    register_asset(env, &record.to_asset);
+    register_pair(env, &record.from_asset, &record.to_asset);
+
+    let last_ts: u64 = storage.get(&DataKey::LastSwapTs).unwrap_or(0);
+    if record.timestamp > last_ts {
+        storage.set(&DataKey::LastSwapTs, &record.timestamp);
+    }
+
+    bump_status_count(env, record.status, false);
+    if record.status == SwapStatus::Pending {
+        let mut pending = pending_swaps(env);
+        insert_sorted(&mut pending, index);
+        storage.set(&DataKey::PendingSwaps, &pending);
+    }
+
+    // Group partial fills under their parent order
+    if let Some(parent) = parent {
+        let key = DataKey::ParentSwaps(parent);
+        let mut fills: Vec<u64> = storage.get(&key).unwrap_or(vec![env]);
+        insert_sorted(&mut fills, index);
+        storage.set(&key, &fills);
+    }
+}
+
+/// Removes the record at `index` from the lookups written by
+/// `index_swap`. Asset and pair registries and the user's first swap
+/// timestamp are left as they are.
+fn unindex_swap(env: &Env, index: u64, record: &SwapRecord) {
+    let storage = env.storage().persistent();
+    storage.remove(&DataKey::SwapById(record.swap_id.clone()));
+
+    let mut user_swaps = user_swap_indices(env, &record.user);
+    if let Ok(position) = user_swaps.binary_search(index) {
+        user_swaps.remove(position);
+        storage.set(&DataKey::UserSwaps(record.user.clone()), &user_swaps);
+    }
+
+    bump_status_count(env, record.status, true);
+    remove_pending(env, index);
+
+    if let Ok(Some(parent)) = parent_key(&record.parent_id) {
+        let key = DataKey::ParentSwaps(parent);
+        let mut fills: Vec<u64> = storage.get(&key).unwrap_or(vec![env]);
+        if let Ok(position) = fills.binary_search(index) {
+            fills.remove(position);
+            storage.set(&key, &fills);
+        }
+    }
+}
+
+/// Inserts `value` into the sorted `list` unless already present.
+fn insert_sorted(list: &mut Vec<u64>, value: u64) {
+    if let Err(position) = list.binary_search(value) {
+        list.insert(position, value);
+    }
+}
+
+/// Emits `user_milestone` the first time `user`'s volume reaches the
+/// configured threshold.
+fn check_user_milestone(env: &Env, user: &Address) {
+    let threshold = SwapTrackerContract::get_user_milestone(env.clone());
+    if threshold <= 0 {
+        return;
+    }
+    let volume = SwapTrackerContract::get_user_volume(env.clone(), user.clone());
+    let key = DataKey::UserMilestoneHit(user.clone());
+    let hit: Option<i128> = env.storage().persistent().get(&key);
+    if volume < threshold || hit == Some(threshold) {
+        return;
+    }
+
+    env.storage().persistent().set(&key, &threshold);
+    publish_event(
+        env,
+        Symbol::new(env, "user_milestone"),
+        (user.clone(), volume),
+    );
+}
+
+/// Publishes a contract event under the topics `(name, EVENT_VERSION)`.
+fn publish_event<D: IntoVal<Env, Val>>(env: &Env, name: Symbol, data: D) {
+    env.events().publish((name, EVENT_VERSION), data);
+}
+
+/// Loads the admin and requires its authorization.
+fn require_admin(env: &Env) -> Result<Address, Error> {
+    let storage = env.storage().instance();
+    if storage.has(&ConfigKey::AdminRenounced) {
+        return Err(Error::NoAdmin);
+    }
+    let admin: Address = storage
+        .get(&ConfigKey::Admin)
+        .ok_or(Error::NotInitialized)?;
+    admin.require_auth();
+    Ok(admin)
+}
+
+/// Aborts the invocation with `Error::Frozen` while the contract is frozen.
+fn ensure_not_frozen(env: &Env) {
+    if SwapTrackerContract::is_frozen(env.clone()) {
+        panic_with_error!(env, Error::Frozen);
+    }
+}
+
+/// Reads the swap record stored at `index`, if any. Records still in the
+/// legacy layout are decoded with defaults for the fields it lacked.
+fn read_swap(env: &Env, index: u64) -> Option<SwapRecord> {
+    let raw: Val = env.storage().persistent().get(&DataKey::Swap(index))?;
+    if is_legacy_layout(env, &raw) {
+        let legacy = LegacySwap::try_from_val(env, &raw).ok()?;
+        return Some(upgrade_legacy_swap(env, index, legacy));
+    }
+    let stored = StoredSwap::try_from_val(env, &raw).ok()?;
+    let codes = asset_codes(env);
+    Some(SwapRecord {
+        user: stored.user,
+        from_asset: codes.get_unchecked(stored.from_asset),
+        to_asset: codes.get_unchecked(stored.to_asset),
+        amount: stored.amount,
+        to_amount: stored.to_amount,
+        timestamp: stored.timestamp,
+        category: stored.category,
+        status: stored.status,
+        confirmations: stored.confirmations,
+        swap_id: stored.swap_id,
+        ledger_seq: stored.ledger_seq,
+        parent_id: stored.parent_id,
+        app_id: stored.app_id,
+        deadline_ts: stored.deadline_ts,
+        quote_value: stored.quote_value,
+        fee: stored.fee,
+        prev_hash: stored.prev_hash,
+    })
+}
+
+/// Returns whether a raw `Swap(i)` entry uses the legacy layout, which
+/// has no `swap_id` field. The host traps instead of failing when a struct
+/// is decoded from a map with a different field count, so the layout must
+/// be checked before decoding.
+fn is_legacy_layout(env: &Env, raw: &Val) -> bool {
+    Map::<Symbol, Val>::try_from_val(env, raw)
+        .is_ok_and(|fields| !fields.contains_key(symbol_short!("swap_id")))
+}
+
+/// Converts a legacy record at `index` into a `SwapRecord`. It is treated
+/// as a completed swap without a counter amount, parent, deadline or
+/// quote value, under the `legacy` category and app id.
+fn upgrade_legacy_swap(env: &Env, index: u64, legacy: LegacySwap) -> SwapRecord {
+    let swap_id = compute_swap_id(
+        env,
+        &legacy.user,
+        &legacy.from_asset,
+        &legacy.to_asset,
+        legacy.amount,
+        legacy.timestamp,
+        index,
+    );
+    SwapRecord {
+        user: legacy.user,
+        from_asset: legacy.from_asset,
+        to_asset: legacy.to_asset,
+        amount: legacy.amount,
+        to_amount: 0,
+        timestamp: legacy.timestamp,
+        category: LEGACY_TAG,
+        status: SwapStatus::Completed,
+        confirmations: 0,
+        swap_id,
+        ledger_seq: 0,
+        parent_id: None,
+        app_id: LEGACY_TAG,
+        deadline_ts: 0,
+        quote_value: 0,
+        fee: 0,
+        prev_hash: BytesN::from_array(env, &[0; 32]),
+    }
+}
+
+/// Reads the swap record at `index` for returning to callers, with the
+/// user replaced by a placeholder if they have opted out of reads.
+fn read_public_swap(env: &Env, index: u64) -> Option<SwapRecord> {
+    let mut record = read_swap(env, index)?;
+    if SwapTrackerContract::is_private(env.clone(), record.user.clone()) {
+        record.user = Address::from_string(&String::from_str(env, PRIVATE_PLACEHOLDER));
+    }
+    Some(record)
+}
+
+/// Returns the asset codes referenced by stored records, indexed by id.
+fn asset_codes(env: &Env) -> Vec<String> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::AssetCodes)
+        .unwrap_or(vec![env])
+}
+
+/// Returns the registry id of `asset`, assigning the next one if new.
+///
+/// Unlike the asset list, the registry is never pruned so ids held by
+/// stored records stay valid after `merge_asset`.
+fn asset_code_id(env: &Env, asset: &String) -> u32 {
+    let mut codes = asset_codes(env);
+    if let Some(id) = codes.first_index_of(asset) {
+        return id;
+    }
+    codes.push_back(asset.clone());
+    env.storage().persistent().set(&DataKey::AssetCodes, &codes);
+    codes.len() - 1
+}
+
+/// Hashes the parts of a swap used to detect client retries.
+fn swap_fingerprint(env: &Env, from_asset: &String, to_asset: &String, amount: i128) -> BytesN<32> {
+    let payload = (from_asset.clone(), to_asset.clone(), amount).to_xdr(env);
+    env.crypto().sha256(&payload).into()
+}
+
+/// Hashes the contents that identify a swap for replay protection.
+fn idempotency_key(
+    env: &Env,
+    user: &Address,
+    from_asset: &String,
+    to_asset: &String,
+    amount: i128,
+    timestamp: u64,
+) -> BytesN<32> {
+    let payload = (
+        user.clone(),
+        from_asset.clone(),
+        to_asset.clone(),
+        amount,
+        timestamp,
+    )
+        .to_xdr(env);
+    env.crypto().sha256(&payload).into()
+}
+
+/// Sets the pre-filter bits for `hash` and returns whether they were all
+/// already set.
+///
+/// The filter is a fixed-size Bloom filter over idempotency keys, so a
+/// `false` result proves the key was never seen. A `true` result may be a
+/// false positive, which is why callers confirm it against `SeenHash`.
+/// Bits are never cleared, so false positives grow with volume and the
+/// exact check is hit more often; correctness is unaffected.
+fn dup_filter_check_and_set(env: &Env, hash: &BytesN<32>) -> bool {
+    let storage = env.storage().persistent();
+    let mut filter: Bytes = storage.get(&DataKey::DupFilter).unwrap_or_else(|| {
+        let mut empty = Bytes::new(env);
+        for _ in 0..DUP_FILTER_BYTES {
+            empty.push_back(0);
+        }
+        empty
+    });
+
+    let mut seen = true;
+    for i in 0..DUP_FILTER_HASHES {
+        let high = hash.get_unchecked(2 * i) as u32;
+        let low = hash.get_unchecked(2 * i + 1) as u32;
+        let bit = ((high << 8) | low) % (DUP_FILTER_BYTES * 8);
+        let (byte, mask) = (bit / 8, 1u8 << (bit % 8));
+        let current = filter.get_unchecked(byte);
+        if current & mask == 0 {
+            seen = false;
+            filter.set(byte, current | mask);
+        }
+    }
+
+    if !seen {
+        storage.set(&DataKey::DupFilter, &filter);
+    }
+    seen
+}
+
+/// Returns `to_amount / amount` multiplied by `scale` and rounded per
+/// `mode`, or `None` when `amount` is not positive.
+fn compute_rate(scale: i128, mode: RoundingMode, amount: i128, to_amount: i128) -> Option<i128> {
+    if amount <= 0 {
+        return None;
+    }
+    to_amount
+        .checked_mul(scale)
+        .map(|scaled| div_round(scaled, amount, mode))
+}
+
+/// Divides `num` by a positive `den`, rounding per `mode`. `Nearest`
+/// rounds halves up.
+fn div_round(num: i128, den: i128, mode: RoundingMode) -> i128 {
+    let quotient = num.div_euclid(den);
+    let remainder = num.rem_euclid(den);
+    match mode {
+        RoundingMode::Floor => quotient,
+        RoundingMode::Ceil if remainder > 0 => quotient + 1,
+        RoundingMode::Ceil => quotient,
+        RoundingMode::Nearest if remainder >= den - remainder => quotient + 1,
+        RoundingMode::Nearest => quotient,
+    }
+}
+
+/// Returns the number of swaps recorded on `day`.
+fn daily_count(env: &Env, day: u64) -> u32 {
+    read_aggregate(env, &DayKey::Count(day)).unwrap_or(0)
+}
+
+/// Returns the total volume of swaps `0..=index` as stored when `index`
+/// was recorded.
+fn cumulative_volume(env: &Env, index: u64) -> Option<i128> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::CumulativeVolume(index))
+}
+
+/// Adds `delta` to the volume of every record written so far and returns
+/// the new total. Deployments that predate the running total start from
+/// the total stored at the last index. Call it before the swap count moves.
+fn add_running_volume(env: &Env, delta: i128) -> i128 {
+    let running = env
+        .storage()
+        .instance()
+        .get(&ConfigKey::RunningVolume)
+        .unwrap_or_else(|| {
+            SwapTrackerContract::get_swap_count(env.clone())
+                .checked_sub(1)
+                .and_then(|last| cumulative_volume(env, last))
+                .unwrap_or(0)
+        })
+        .saturating_add(delta);
+    env.storage()
+        .instance()
+        .set(&ConfigKey::RunningVolume, &running);
+    running
+}
+
+/// Returns the sorted indices of days with at least one swap.
+fn active_days(env: &Env) -> Vec<u64> {
+    read_aggregate(env, &DayKey::Active).unwrap_or(vec![env])
+}
+
+/// Returns the total amount swapped on `day`.
+fn daily_volume(env: &Env, day: u64) -> i128 {
+    read_aggregate(env, &DayKey::Volume(day)).unwrap_or(0)
+}
+
+/// Returns whether swaps selling `from` for `to` may be recorded under the
+/// current pair allowlist setting.
+fn pair_permitted(env: &Env, from: &String, to: &String) -> bool {
+    !SwapTrackerContract::is_pair_allowlist_enabled(env.clone())
+        || SwapTrackerContract::is_pair_approved(env.clone(), from.clone(), to.clone())
+}
+
+/// Returns the sorted indices of swaps still `Pending`.
+fn pending_swaps(env: &Env) -> Vec<u64> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::PendingSwaps)
+        .unwrap_or(vec![env])
+}
+
+/// Moves `record` to `status`, moving its user outcome and status count
+/// along and keeping the pending index in step. The caller writes the
+/// record.
+fn change_status(env: &Env, index: u64, record: &mut SwapRecord, status: SwapStatus) {
+    let aggregate = SwapTrackerContract::is_aggregation_enabled(env.clone());
+    if aggregate {
+        bump_user_outcome(env, record, true);
+    }
+    bump_status_count(env, record.status, true);
+
+    record.status = status;
+    if aggregate {
+        bump_user_outcome(env, record, false);
+    }
+    bump_status_count(env, status, false);
+
+    if status == SwapStatus::Pending {
+        let mut pending = pending_swaps(env);
+        insert_sorted(&mut pending, index);
+        env.storage()
+            .persistent()
+            .set(&DataKey::PendingSwaps, &pending);
+    } else {
+        remove_pending(env, index);
+    }
+}
+
+/// Counts one more stored record with `status`, or one fewer when
+/// `remove` is set.
+fn bump_status_count(env: &Env, status: SwapStatus, remove: bool) {
+    let count = SwapTrackerContract::get_status_count(env.clone(), status);
+    env.storage()
+        .persistent()
+        .set(&DataKey::StatusCount(status), &step(count, remove));
+}
+
+/// Drops `index` from the pending index if present.
+fn remove_pending(env: &Env, index: u64) {
+    let mut pending = pending_swaps(env);
+    if let Ok(position) = pending.binary_search(index) {
+        pending.remove(position);
+        env.storage()
+            .persistent()
+            .set(&DataKey::PendingSwaps, &pending);
+    }
+}
+
+/// Returns `(completed, failed)` swap counts for `user`.
+fn user_outcomes(env: &Env, user: &Address) -> (u32, u32) {
+    read_aggregate(env, &DataKey::UserOutcomes(user.clone())).unwrap_or((0, 0))
+}
+
+/// Counts the record's settled status towards its user's outcomes, or
+/// takes it back when `remove` is set. Pending records are ignored.
+fn bump_user_outcome(env: &Env, record: &SwapRecord, remove: bool) {
+    let (completed, failed) = user_outcomes(env, &record.user);
+    let outcomes = match record.status {
+        SwapStatus::Completed => (step(completed as u64, remove) as u32, failed),
+        SwapStatus::Failed => (completed, step(failed as u64, remove) as u32),
+        SwapStatus::Pending => return,
+    };
+    env.storage()
+        .persistent()
+        .set(&DataKey::UserOutcomes(record.user.clone()), &outcomes);
+}
+
+/// Reads an aggregate, treating a value that no longer decodes as `V`
+/// (e.g. after a schema change) the same as a missing one.
+fn read_aggregate<K, V>(env: &Env, key: &K) -> Option<V>
+where
+    K: IntoVal<Env, Val>,
+    V: TryFromVal<Env, Val>,
+{
+    let raw: Val = env.storage().persistent().get(key)?;
+    V::try_from_val(env, &raw).ok()
+}
+
+/// Returns the amount histogram, one count per power-of-ten bucket.
+fn amount_histogram(env: &Env) -> Vec<u64> {
+    read_aggregate(env, &DataKey::AmountHistogram)
+        .filter(|histogram: &Vec<u64>| histogram.len() == AMOUNT_BUCKETS)
+        .unwrap_or_else(|| {
+            let mut empty = vec![env];
+            for _ in 0..AMOUNT_BUCKETS {
+                empty.push_back(0);
+            }
+            empty
+        })
+}
+
+/// Returns the power-of-ten bucket for `amount`. Amounts below 10,
+/// including non-positive ones, fall into bucket 0.
+fn amount_bucket(amount: i128) -> u32 {
+    if amount <= 0 {
+        0
+    } else {
+        amount.ilog10()
+    }
+}
+
+/// Returns the midpoint of bucket `[10^bucket, 10^(bucket+1))`.
+fn bucket_midpoint(bucket: u32) -> i128 {
+    10_i128.saturating_pow(bucket).saturating_mul(11) / 2
+}
+
+/// Returns the list of assets seen in swaps.
+fn asset_list(env: &Env) -> Vec<String> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::AssetList)
+        .unwrap_or(vec![env])
+}
+
+/// Appends `asset` to the asset list if it has not been seen before.
+fn register_asset(env: &Env, asset: &String) {
+    let mut assets = asset_list(env);
+    if !assets.contains(asset) {
+        assets.push_back(asset.clone());
+        env.storage().persistent().set(&DataKey::AssetList, &assets);
+    }
+}
+
+/// Removes the per-asset aggregates of `asset`: volume, flow counts, fees
+/// and last rate.
+fn clear_asset_aggregates(env: &Env, asset: &String) {
+    let storage = env.storage().persistent();
+    storage.remove(&DataKey::AssetVolume(asset.clone()));
+    storage.remove(&DataKey::AssetOutCount(asset.clone()));
+    storage.remove(&DataKey::AssetInCount(asset.clone()));
+    storage.remove(&DataKey::AssetFees(asset.clone()));
+    storage.remove(&DataKey::AssetLastRate(asset.clone()));
+}
+
+/// Returns every distinct `(from, to)` pair seen in swaps.
+fn pair_list(env: &Env) -> Vec<(String, String)> {
+    env.storage()
+        .persistent()
+        .get(&PairKey::List)
+        .unwrap_or(vec![env])
+}
+
+/// Appends the `(from, to)` pair to the pair list if it has not been seen
+/// before.
+fn register_pair(env: &Env, from: &String, to: &String) {
+    let pair = (from.clone(), to.clone());
+    let mut pairs = pair_list(env);
+    if !pairs.contains(&pair) {
+        pairs.push_back(pair);
+        env.storage().persistent().set(&PairKey::List, &pairs);
+    }
+}
+
+/// Stores `record` at `index` and bumps its TTL per the TTL policy.
+fn write_swap(env: &Env, index: u64, record: &SwapRecord) {
+    let stored = StoredSwap {
+        user: record.user.clone(),
+        from_asset: asset_code_id(env, &record.from_asset),
+        to_asset: asset_code_id(env, &record.to_asset),
+        amount: record.amount,
+        to_amount: record.to_amount,
+        timestamp: record.timestamp,
+        category: record.category.clone(),
+        status: record.status,
+        confirmations: record.confirmations,
+        swap_id: record.swap_id.clone(),
+        ledger_seq: record.ledger_seq,
+        parent_id: record.parent_id.clone(),
+        app_id: record.app_id.clone(),
+        deadline_ts: record.deadline_ts,
+        quote_value: record.quote_value,
+        fee: record.fee,
+        prev_hash: record.prev_hash.clone(),
+    };
+    let key = DataKey::Swap(index);
+    env.storage().persistent().set(&key, &stored);
+    extend_ttl(env, &key);
+}
+
+/// Extends the TTL of a persistent entry per the configured policy.
+fn extend_ttl(env: &Env, key: &DataKey) {
+    let (threshold, extend_to) = SwapTrackerContract::get_ttl_policy(env.clone());
+    let extend_to = extend_to.min(env.storage().max_ttl());
+    env.storage()
+        .persistent()
+        .extend_ttl(key, threshold.min(extend_to), extend_to);
+}
+
+/// Derives the content-addressed id of a swap.
+fn compute_swap_id(
+    env: &Env,
+    user: &Address,
+    from_asset: &String,
+    to_asset: &String,
+    amount: i128,
+    timestamp: u64,
+    index: u64,
+) -> BytesN<32> {
+    let payload = (
+        user.clone(),
+        from_asset.clone(),
+        to_asset.clone(),
+        amount,
+        timestamp,
+        index,
+    )
+        .to_xdr(env);
+    env.crypto().sha256(&payload).into()
+}
+
+/// Hashes the fields of `record` fixed at recording time, including its
+/// swap id, together with its link to the previous record. Status and
+/// confirmations change as a swap settles and are left out.
+fn record_hash(env: &Env, record: &SwapRecord) -> BytesN<32> {
+    let payload = (
+        record.prev_hash.clone(),
+        record.user.clone(),
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+        record.amount,
+        record.to_amount,
+        record.timestamp,
+        record.category.clone(),
+        record.ledger_seq,
+        record.parent_id.clone(),
+        record.app_id.clone(),
+        record.deadline_ts,
+        (record.swap_id.clone(), record.quote_value, record.fee),
+    )
+        .to_xdr(env);
+    env.crypto().sha256(&payload).into()
+}
+
+/// Returns the hash of the most recently recorded swap, or all zeroes
+/// before the first one.
+fn chain_head(env: &Env) -> BytesN<32> {
+    env.storage()
+        .instance()
+        .get(&ConfigKey::ChainHead)
+        .unwrap_or(BytesN::from_array(env, &[0; 32]))
+}
+
+/// Returns the indices of `user`'s swaps in recording order.
+fn user_swap_indices(env: &Env, user: &Address) -> Vec<u64> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::UserSwaps(user.clone()))
+        .unwrap_or(vec![env])
+}
+
+/// Removes the keyed aggregates a record contributes to.
+fn clear_aggregates(env: &Env, record: &SwapRecord) {
+    let storage = env.storage().persistent();
+    storage.remove(&DataKey::UserSwapCount(record.user.clone()));
+    storage.remove(&DataKey::UserVolume(record.user.clone()));
+    storage.remove(&DataKey::UserLastTs(record.user.clone()));
+    storage.remove(&DataKey::UserOutcomes(record.user.clone()));
+    storage.remove(&DataKey::CategoryCount(record.category.clone()));
+    storage.remove(&DataKey::AppCount(record.app_id.clone()));
+    storage.remove(&DataKey::AppVolume(record.app_id.clone()));
+    storage.remove(&DataKey::UserAssetVolume(
+        record.user.clone(),
+        record.from_asset.clone(),
+    ));
+    storage.remove(&DayKey::Count(record.timestamp / SECONDS_PER_DAY));
+    storage.remove(&DayKey::Volume(record.timestamp / SECONDS_PER_DAY));
+    storage.remove(&DayKey::HourVolume(record.timestamp / SECONDS_PER_HOUR));
+    storage.remove(&DataKey::AssetOutCount(record.from_asset.clone()));
+    storage.remove(&DataKey::AssetInCount(record.to_asset.clone()));
+    storage.remove(&DataKey::AssetFees(record.from_asset.clone()));
+    storage.remove(&DataKey::AssetVolume(record.from_asset.clone()));
+    storage.remove(&DataKey::AssetLastRate(record.from_asset.clone()));
+    storage.remove(&PairKey::LastRate(
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+    ));
+    storage.remove(&PairKey::Tick(
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+    ));
+    storage.remove(&PairKey::RateMin(
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+    ));
+    storage.remove(&PairKey::RateMax(
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+    ));
+    storage.remove(&PairKey::Candle(
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+        record.timestamp / SECONDS_PER_DAY,
+    ));
+    storage.remove(&PairKey::Volume(
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+    ));
+    storage.remove(&PairKey::Count(
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+    ));
+}
+
+/// Folds a newly stored record into the maintained aggregates and returns
+/// the pair's resulting tick direction.
+fn update_aggregates(env: &Env, record: &SwapRecord) -> Result<TickDirection, Error> {
+    let storage = env.storage().persistent();
+    apply_counters(env, record, false)?;
+
+    if record.amount > SwapTrackerContract::get_max_swap_amount(env.clone()) {
+        storage.set(&DataKey::MaxAmount, &record.amount);
+    }
+    storage.set(&DataKey::UserLastTs(record.user.clone()), &record.timestamp);
+
+    let day = record.timestamp / SECONDS_PER_DAY;
+    let day_volume = daily_volume(env, day);
+    let busiest: Option<(u64, i128)> = read_aggregate(env, &DayKey::Busiest);
+    if busiest.map_or(true, |(_, volume)| day_volume > volume) {
+        storage.set(&DayKey::Busiest, &(day, day_volume));
+    }
+
+    let scale = SwapTrackerContract::get_rate_scale(env.clone());
+    let mode = SwapTrackerContract::get_rounding_mode(env.clone());
+    let Some(rate) = compute_rate(scale, mode, record.amount, record.to_amount) else {
+        return Ok(TickDirection::Flat);
+    };
+    storage.set(&DataKey::AssetLastRate(record.from_asset.clone()), &rate);
+    update_rate_range(env, record, rate);
+    update_candle(env, record, rate);
+    Ok(update_pair_tick(env, record, rate))
+}
+
+/// Adds the record's contribution to the additive aggregates (volumes,
+/// counts, the histogram and active days), or subtracts it when `remove`
+/// is set. Returns `Error::Overflow` if a total would leave the `i128`
+/// range.
+fn apply_counters(env: &Env, record: &SwapRecord, remove: bool) -> Result<(), Error> {
+    let storage = env.storage().persistent();
+    let amount = if remove {
+        -record.amount
+    } else {
+        record.amount
+    };
+
+    let total = SwapTrackerContract::get_total_volume(env.clone());
+    storage.set(&DataKey::TotalVolume, &checked_total(total, amount)?);
+
+    let quote_value = if remove {
+        -record.quote_value
+    } else {
+        record.quote_value
+    };
+    let total_quote = SwapTrackerContract::get_total_quote_value(env.clone());
+    storage.set(
+        &DataKey::TotalQuoteValue,
+        &checked_total(total_quote, quote_value)?,
+    );
+
+    let user = &record.user;
+    let user_count = SwapTrackerContract::get_user_swap_count(env.clone(), user.clone());
+    storage.set(
+        &DataKey::UserSwapCount(user.clone()),
+        &step(user_count, remove),
+    );
+
+    let user_volume = SwapTrackerContract::get_user_volume(env.clone(), user.clone());
+    storage.set(
+        &DataKey::UserVolume(user.clone()),
+        &checked_total(user_volume, amount)?,
+    );
+
+    // (v + a)^2 - v^2 = a * (2v + a)
+    let sum_squares: i128 = read_aggregate(env, &DataKey::VolumeSumSquares).unwrap_or(0);
+    let delta = amount.saturating_mul(user_volume.saturating_mul(2).saturating_add(amount));
+    storage.set(
+        &DataKey::VolumeSumSquares,
+        &sum_squares.saturating_add(delta),
+    );
+
+    bump_user_outcome(env, record, remove);
+
+    let user_asset_volume = SwapTrackerContract::get_user_asset_volume(
+        env.clone(),
+        user.clone(),
+        record.from_asset.clone(),
+    );
+    storage.set(
+        &DataKey::UserAssetVolume(user.clone(), record.from_asset.clone()),
+        &checked_total(user_asset_volume, amount)?,
+    );
+
+    let day = record.timestamp / SECONDS_PER_DAY;
+    let day_count = daily_count(env, day);
+    let new_day_count = step(day_count as u64, remove) as u32;
+    if (day_count == 0) != (new_day_count == 0) {
+        let mut days = active_days(env);
+        if remove {
+            if let Ok(position) = days.binary_search(day) {
+                days.remove(position);
+            }
+        } else {
+            insert_sorted(&mut days, day);
+        }
+        storage.set(&DayKey::Active, &days);
+        let active_count = SwapTrackerContract::get_active_day_count(env.clone());
+        storage.set(
+            &DayKey::ActiveCount,
+            &(step(active_count as u64, remove) as u32),
+        );
+    }
+    storage.set(&DayKey::Count(day), &new_day_count);
+    storage.set(
+        &DayKey::Volume(day),
+        &checked_total(daily_volume(env, day), amount)?,
+    );
+    let hour = record.timestamp / SECONDS_PER_HOUR;
+    let hour_volume: i128 = read_aggregate(env, &DayKey::HourVolume(hour)).unwrap_or(0);
+    storage.set(
+        &DayKey::HourVolume(hour),
+        &checked_total(hour_volume, amount)?,
+    );
+
+    let mut histogram = amount_histogram(env);
+    let bucket = amount_bucket(record.amount);
+    histogram.set(bucket, step(histogram.get_unchecked(bucket), remove));
+    storage.set(&DataKey::AmountHistogram, &histogram);
+
+    let (out_count, _) =
+        SwapTrackerContract::get_asset_flow_counts(env.clone(), record.from_asset.clone());
+    storage.set(
+        &DataKey::AssetOutCount(record.from_asset.clone()),
+        &step(out_count, remove),
+    );
+    let (_, in_count) =
+        SwapTrackerContract::get_asset_flow_counts(env.clone(), record.to_asset.clone());
+    storage.set(
+        &DataKey::AssetInCount(record.to_asset.clone()),
+        &step(in_count, remove),
+    );
+
+    let asset_volume: i128 =
+        read_aggregate(env, &DataKey::AssetVolume(record.from_asset.clone())).unwrap_or(0);
+    storage.set(
+        &DataKey::AssetVolume(record.from_asset.clone()),
+        &checked_total(asset_volume, amount)?,
+    );
+
+    let pair_volume = SwapTrackerContract::get_pair_volume(
+        env.clone(),
+        record.from_asset.clone(),
+        record.to_asset.clone(),
+    );
+    storage.set(
+        &PairKey::Volume(record.from_asset.clone(), record.to_asset.clone()),
+        &checked_total(pair_volume, amount)?,
+    );
+    let pair_count: u64 = read_aggregate(
+        env,
+        &PairKey::Count(record.from_asset.clone(), record.to_asset.clone()),
+    )
+    .unwrap_or(0);
+    storage.set(
+        &PairKey::Count(record.from_asset.clone(), record.to_asset.clone()),
+        &step(pair_count, remove),
+    );
+
+    if record.fee != 0 {
+        let fee = if remove { -record.fee } else { record.fee };
+        let fees = SwapTrackerContract::get_asset_fees(env.clone(), record.from_asset.clone());
+        storage.set(
+            &DataKey::AssetFees(record.from_asset.clone()),
+            &checked_total(fees, fee)?,
+        );
+    }
+
+    let app_id = &record.app_id;
+    storage.set(
+        &DataKey::AppCount(app_id.clone()),
+        &step(
+            SwapTrackerContract::get_app_count(env.clone(), app_id.clone()),
+            remove,
+        ),
+    );
+    storage.set(
+        &DataKey::AppVolume(app_id.clone()),
+        &checked_total(
+            SwapTrackerContract::get_app_volume(env.clone(), app_id.clone()),
+            amount,
+        )?,
+    );
+
+    let category_count =
+        SwapTrackerContract::get_category_count(env.clone(), record.category.clone());
+    storage.set(
+        &DataKey::CategoryCount(record.category.clone()),
+        &step(category_count, remove),
+    );
+    Ok(())
+}
+
+/// Adds `delta` to a running total, failing with `Error::Overflow` rather
+/// than trapping. Writes made before the error are rolled back with the
+/// failed call.
+fn checked_total(total: i128, delta: i128) -> Result<i128, Error> {
+    total.checked_add(delta).ok_or(Error::Overflow)
+}
+
+/// Moves a counter one up, or one down (saturating) when `remove` is set.
+fn step(count: u64, remove: bool) -> u64 {
+    if remove {
+        count.saturating_sub(1)
+    } else {
+        count + 1
+    }
+}
+
+/// Widens the pair's all-time rate range to include `rate`.
+fn update_rate_range(env: &Env, record: &SwapRecord, rate: i128) {
+    let storage = env.storage().persistent();
+    let pair = (record.from_asset.clone(), record.to_asset.clone());
+    let min_key = PairKey::RateMin(pair.0.clone(), pair.1.clone());
+    let max_key = PairKey::RateMax(pair.0, pair.1);
+
+    let min: Option<i128> = read_aggregate(env, &min_key);
+    if min.map_or(true, |min| rate < min) {
+        storage.set(&min_key, &rate);
+    }
+    let max: Option<i128> = read_aggregate(env, &max_key);
+    if max.map_or(true, |max| rate > max) {
+        storage.set(&max_key, &rate);
+    }
+}
+
+/// Folds `rate` into the pair's candle for the record's day.
+fn update_candle(env: &Env, record: &SwapRecord, rate: i128) {
+    let day = record.timestamp / SECONDS_PER_DAY;
+    let key = PairKey::Candle(record.from_asset.clone(), record.to_asset.clone(), day);
+    let existing: Option<(i128, i128, i128, i128)> = read_aggregate(env, &key);
+    let candle = match existing {
+        Some((open, high, low, _)) => (open, rate.max(high), rate.min(low), rate),
+        None => (rate, rate, rate, rate),
+    };
+    env.storage().persistent().set(&key, &candle);
+}
+
+/// Compares the record's `rate` with the pair's previous rate and stores
+/// both the new rate and the resulting tick.
+fn update_pair_tick(env: &Env, record: &SwapRecord, rate: i128) -> TickDirection {
+    let storage = env.storage().persistent();
+    let pair = (record.from_asset.clone(), record.to_asset.clone());
+    let last: Option<i128> =
+        read_aggregate(env, &PairKey::LastRate(pair.0.clone(), pair.1.clone()));
+    let tick = match last {
+        Some(last) if rate > last => TickDirection::Up,
+        Some(last) if rate < last => TickDirection::Down,
+        _ => TickDirection::Flat,
+    };
+
+    storage.set(&PairKey::LastRate(pair.0.clone(), pair.1.clone()), &rate);
+    storage.set(&PairKey::Tick(pair.0, pair.1), &tick);
+    tick
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::{
+        testutils::{storage::Persistent as _, Address as _, Events, Ledger},
+        xdr::{FromXdr, ToXdr},
+        Env, IntoVal, InvokeError, TryFromVal, Val,
+    };
+
+    /// Hook that keeps the last swap it was notified of, or rejects every
+    /// notification once set to fail.
+    #[contract]
+    struct MockHook;
+
+    #[contractimpl]
+    impl MockHook {
+        pub fn on_swap(env: Env, record: SwapRecord) {
+            if env.storage().instance().has(&symbol_short!("fail")) {
+                panic!("hook rejected swap");
+            }
+            env.storage()
+                .instance()
+                .set(&symbol_short!("last"), &record);
+        }
+
+        pub fn set_failing(env: Env) {
+            env.storage().instance().set(&symbol_short!("fail"), &true);
+        }
+
+        pub fn last_swap(env: Env) -> Option<SwapRecord> {
+            env.storage().instance().get(&symbol_short!("last"))
+        }
+    }
+
+    const CATEGORY: Symbol = symbol_short!("spot");
+    const APP: Symbol = symbol_short!("web");
+
+    /// Registers the contract with an admin and the default test category.
+    fn setup<'a>(env: &Env) -> SwapTrackerContractClient<'a> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, SwapTrackerContract);
+        let client = SwapTrackerContractClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        client.set_category_allowed(&CATEGORY, &true);
+        client.set_app_allowed(&APP, &true);
+        client
+    }
+
+    /// Builds a 1:1 swap under the default test category.
+    fn swap_input(
+        user: &Address,
+        from: &String,
+        to: &String,
+        amount: i128,
+        timestamp: u64,
+    ) -> SwapInput {
+        SwapInput {
+            user: user.clone(),
+            from_asset: from.clone(),
+            to_asset: to.clone(),
+            amount,
+            to_amount: amount,
+            timestamp,
+            category: CATEGORY,
+            parent_id: None,
+            app_id: APP,
+            deadline_ts: 0,
+        }
+    }
+
+    /// Calls `record_swap` with every field of `input`, checking that the
+    /// returned index is the prior swap count.
+    fn submit(client: &SwapTrackerContractClient, input: &SwapInput) -> u64 {
+        let expected = client.get_swap_count();
+        let index = client.record_swap(
+            &input.user,
+            &input.from_asset,
+            &input.to_asset,
+            &input.amount,
+            &input.to_amount,
+            &input.timestamp,
+            &input.category,
+            &parent_key(&input.parent_id).unwrap(),
+            &input.app_id,
+            &input.deadline_ts,
+        );
+        assert_eq!(index, expected);
+        index
+    }
+
+    /// Calls `try_record_swap` with every field of `input`.
+    fn try_submit(
+        client: &SwapTrackerContractClient,
+        input: &SwapInput,
+    ) -> Result<Result<u64, soroban_sdk::Error>, Result<Error, InvokeError>> {
+        client.try_record_swap(
+            &input.user,
+            &input.from_asset,
+            &input.to_asset,
+            &input.amount,
+            &input.to_amount,
+            &input.timestamp,
+            &input.category,
+            &parent_key(&input.parent_id).unwrap(),
+            &input.app_id,
+            &input.deadline_ts,
+        )
+    }
+
+    /// Records a swap under the default test category at a 1:1 rate.
+    fn record(
+        client: &SwapTrackerContractClient,
+        user: &Address,
+        from: &String,
+        to: &String,
+        amount: i128,
+        timestamp: u64,
+    ) -> u64 {
+        submit(client, &swap_input(user, from, to, amount, timestamp))
+    }
+
+    #[test]
+    fn test_record_and_retrieve() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let from = String::from_str(&env, "XLM");
+        let to = String::from_str(&env, "USDC");
+
+        record(&client, &user, &from, &to, 1_000_000, 1700000000);
+
+        assert_eq!(client.get_swap_count(), 1);
+
+        let swaps = client.get_recent_swaps(&1);
+        assert_eq!(swaps.len(), 1);
+
+        let record = swaps.get(0).unwrap();
+        assert_eq!(record.amount, 1_000_000);
+        assert_eq!(record.timestamp, 1700000000);
+    }
+
+    #[test]
+    fn test_multiple_swaps_ordering() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        // Record 3 swaps
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &usdc, &xlm, 200, 2000);
+        record(&client, &user, &xlm, &usdc, 300, 3000);
+
+        assert_eq!(client.get_swap_count(), 3);
+
+        // Get last 2 — should be newest first
+        let swaps = client.get_recent_swaps(&2);
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps.get(0).unwrap().amount, 300);
+        assert_eq!(swaps.get(1).unwrap().amount, 200);
+    }
+
+    #[test]
+    fn test_event_emission() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let from = String::from_str(&env, "XLM");
+        let to = String::from_str(&env, "USDC");
+
+        record(&client, &user, &from, &to, 500, 1700000000);
+
+        // Verify event was emitted
+        let events = env.events().all();
+        assert!(!events.is_empty());
+    }
+
+    #[test]
+    fn test_user_stats() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &usdc, &xlm, 250, 2000);
+
+        let stats = client.get_user_stats(&user);
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.volume, 350);
+        assert_eq!(stats.last_timestamp, 2000);
+
+        // Unknown users get zeroed stats
+        let empty = client.get_user_stats(&other);
+        assert_eq!(
+            empty,
+            UserStats {
+                count: 0,
+                volume: 0,
+                last_timestamp: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_aggregation_toggle() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert!(client.is_aggregation_enabled());
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        assert_eq!(client.get_total_volume(), 100);
+
+        client.set_aggregation_enabled(&false);
+        record(&client, &user, &xlm, &usdc, 500, 2000);
+
+        // Raw record is stored but aggregates are left untouched
+        assert_eq!(client.get_swap_count(), 2);
+        assert_eq!(client.get_total_volume(), 100);
+        assert_eq!(client.get_user_volume(&user), 100);
+    }
+
+    #[test]
+    fn test_initialize_twice_fails() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let admin = Address::generate(&env);
+        assert_eq!(
+            client.try_initialize(&admin),
+            Err(Ok(Error::AlreadyInitialized))
+        );
+    }
+
+    #[test]
+    fn test_rebuild_aggregates() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &usdc, &xlm, 300, 2000);
+
+        // Corrupt the aggregates directly in storage
+        env.as_contract(&client.address, || {
+            let storage = env.storage().persistent();
+            storage.set(&DataKey::TotalVolume, &999_i128);
+            storage.set(&DataKey::UserVolume(user.clone()), &1_i128);
+            storage.set(&DataKey::UserSwapCount(user.clone()), &7_u64);
+        });
+        assert_eq!(client.get_total_volume(), 999);
+
+        client.rebuild_aggregates();
+
+        assert_eq!(client.get_total_volume(), 400);
+        assert_eq!(client.get_max_swap_amount(), 300);
+        let stats = client.get_user_stats(&user);
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.volume, 400);
+        assert_eq!(stats.last_timestamp, 2000);
+    }
+
+    #[test]
+    fn test_user_swaps_by_asset() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let eurc = String::from_str(&env, "EURC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &other, &xlm, &usdc, 200, 2000);
+        record(&client, &user, &eurc, &xlm, 300, 3000);
+
+        let swaps = client.get_user_swaps_by_asset(&user, &usdc, &10);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps.get(0).unwrap().amount, 100);
+
+        // XLM appears on both of the user's swaps, newest first
+        let swaps = client.get_user_swaps_by_asset(&user, &xlm, &10);
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps.get(0).unwrap().amount, 300);
+        assert_eq!(swaps.get(1).unwrap().amount, 100);
+    }
+
+    #[test]
+    fn test_to_stroops() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        assert_eq!(client.to_stroops(&5, &7), 50_000_000);
+        assert_eq!(client.to_stroops(&-3, &2), -300);
+        assert_eq!(client.to_stroops(&42, &0), 42);
+
+        assert_eq!(
+            client.try_to_stroops(&i128::MAX, &7),
+            Err(Ok(Error::Overflow))
+        );
+        assert_eq!(client.try_to_stroops(&1, &39), Err(Ok(Error::Overflow)));
+    }
+
+    #[test]
+    fn test_circuit_breaker_pauses() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_circuit_multiplier(&10);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &xlm, &usdc, 120, 2000);
+        assert!(!client.is_paused());
+
+        // 100x the running average trips the breaker
+        record(&client, &user, &xlm, &usdc, 11_000, 3000);
+        assert!(client.is_paused());
+        assert_eq!(client.get_swap_count(), 3);
+
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 100, 4000)),
+            Err(Ok(Error::Paused))
+        );
+
+        client.unpause();
+        record(&client, &user, &xlm, &usdc, 100, 4000);
+        assert_eq!(client.get_swap_count(), 4);
+    }
+
+    #[test]
+    fn test_user_swap_indices() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        assert!(client.get_user_swap_indices(&user).is_empty());
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &other, &xlm, &usdc, 100, 1500);
+        record(&client, &user, &usdc, &xlm, 200, 2000);
+        record(&client, &user, &xlm, &usdc, 300, 3000);
+
+        assert_eq!(client.get_user_swap_indices(&user), vec![&env, 0, 2, 3]);
+        assert_eq!(client.get_user_swap_indices(&other), vec![&env, 1]);
+    }
+
+    #[test]
+    fn test_swap_categories() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let arb = symbol_short!("arbitrage");
+        let dca = symbol_short!("dca");
+        client.set_category_allowed(&arb, &true);
+        client.set_category_allowed(&dca, &true);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        submit(
+            &client,
+            &SwapInput {
+                category: arb.clone(),
+                ..swap_input(&user, &xlm, &usdc, 100, 1000)
+            },
+        );
+        submit(
+            &client,
+            &SwapInput {
+                category: dca.clone(),
+                ..swap_input(&user, &usdc, &xlm, 200, 2000)
+            },
+        );
+        submit(
+            &client,
+            &SwapInput {
+                category: arb.clone(),
+                ..swap_input(&user, &xlm, &usdc, 300, 3000)
+            },
+        );
+
+        assert_eq!(client.get_category_count(&arb), 2);
+        assert_eq!(client.get_category_count(&dca), 1);
+
+        let swaps = client.get_swaps_by_category(&arb, &10);
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps.get(0).unwrap().amount, 300);
+        assert_eq!(swaps.get(1).unwrap().amount, 100);
+
+        let swaps = client.get_swaps_by_category(&dca, &10);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps.get(0).unwrap().category, dca);
+
+        // Categories outside the allowed set are rejected
+        client.set_category_allowed(&dca, &false);
+        assert_eq!(
+            try_submit(
+                &client,
+                &SwapInput {
+                    category: dca.clone(),
+                    ..swap_input(&user, &xlm, &usdc, 100, 4000)
+                }
+            ),
+            Err(Ok(Error::CategoryNotAllowed))
+        );
+        assert_eq!(
+            try_submit(
+                &client,
+                &SwapInput {
+                    category: symbol_short!("other"),
+                    ..swap_input(&user, &xlm, &usdc, 100, 4000)
+                }
+            ),
+            Err(Ok(Error::CategoryNotAllowed))
+        );
+    }
+
+    #[test]
+    fn test_health_check() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert!(client.health_check());
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &usdc, &xlm, 200, 2000);
+        assert!(client.health_check());
+
+        // SwapCount pointing past the stored records
+        env.as_contract(&client.address, || {
+            env.storage().persistent().set(&DataKey::SwapCount, &5_u64);
+        });
+        assert!(!client.health_check());
+
+        // SwapCount lagging behind the stored records
+        env.as_contract(&client.address, || {
+            env.storage().persistent().set(&DataKey::SwapCount, &1_u64);
+        });
+        assert!(!client.health_check());
+
+        env.as_contract(&client.address, || {
+            env.storage().persistent().set(&DataKey::SwapCount, &2_u64);
+            env.storage()
+                .persistent()
+                .set(&DataKey::TotalVolume, &-1_i128);
+        });
+        assert!(!client.health_check());
+    }
+
+    #[test]
+    fn test_cursor_pagination() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        for i in 1..=5_u64 {
+            record(&client, &user, &xlm, &usdc, i as i128 * 100, i * 1000);
+        }
+
+        let mut amounts = Vec::<i128>::new(&env);
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let page = client.get_cursor_page(&cursor, &2);
+            pages += 1;
+            for record in page.records.iter() {
+                amounts.push_back(record.amount);
+            }
+            assert_eq!(page.has_more, page.next_cursor.is_some());
+            if !page.has_more {
+                break;
+            }
+            cursor = page.next_cursor;
+        }
+
+        assert_eq!(pages, 3);
+        assert_eq!(amounts, vec![&env, 500, 400, 300, 200, 100]);
+    }
+
+    #[test]
+    fn test_duplicate_swap_window() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_duplicate_window(&60);
+        env.ledger().set_timestamp(10_000);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+
+        // Identical swap immediately after is rejected
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1001)),
+            Err(Ok(Error::DuplicateTooSoon))
+        );
+
+        // A different amount is allowed
+        record(&client, &user, &xlm, &usdc, 101, 1002);
+
+        // Another user may record the same swap
+        let other = Address::generate(&env);
+        record(&client, &other, &xlm, &usdc, 101, 1003);
+
+        // Identical swap after the window has passed is allowed
+        env.ledger().set_timestamp(10_060);
+        record(&client, &user, &xlm, &usdc, 101, 1060);
+        assert_eq!(client.get_swap_count(), 4);
+    }
+
+    #[test]
+    fn test_user_asset_volume() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &xlm, &usdc, 250, 2000);
+        record(&client, &user, &usdc, &xlm, 40, 3000);
+        record(&client, &other, &xlm, &usdc, 999, 4000);
+
+        assert_eq!(client.get_user_asset_volume(&user, &xlm), 350);
+        assert_eq!(client.get_user_asset_volume(&user, &usdc), 40);
+        assert_eq!(client.get_user_asset_volume(&other, &xlm), 999);
+        assert_eq!(client.get_user_asset_volume(&other, &usdc), 0);
+    }
+
+    /// Reads a big-endian `u32` from `bytes` at `offset`.
+    fn read_u32(bytes: &Bytes, offset: u32) -> u32 {
+        let mut buf = [0u8; 4];
+        bytes.slice(offset..offset + 4).copy_into_slice(&mut buf);
+        u32::from_be_bytes(buf)
+    }
+
+    #[test]
+    fn test_export_range() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &usdc, &xlm, 200, 2000);
+        record(&client, &user, &xlm, &usdc, 300, 3000);
+
+        let packed = client.export_range(&1, &10);
+        assert_eq!(read_u32(&packed, 0), 2);
+
+        let mut offset = 4;
+        let mut decoded = Vec::<SwapRecord>::new(&env);
+        while offset < packed.len() {
+            let len = read_u32(&packed, offset);
+            offset += 4;
+            let entry = packed.slice(offset..offset + len);
+            decoded.push_back(SwapRecord::from_xdr(&env, &entry).unwrap());
+            offset += len;
+        }
+
+        assert_eq!(decoded.len(), 2);
+        let first = decoded.get(0).unwrap();
+        assert_eq!(first.amount, 200);
+        assert_eq!(first.from_asset, usdc);
+        assert_eq!(first.to_asset, xlm);
+        assert_eq!(first.timestamp, 2000);
+        assert_eq!(decoded.get(1).unwrap().amount, 300);
+        assert_eq!(decoded.get(1).unwrap().user, user);
+    }
+
+    #[test]
+    fn test_confirmations_complete_swap() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_confirmation_threshold(&3);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        assert_eq!(client.get_swap(&0).unwrap().status, SwapStatus::Pending);
+
+        client.add_confirmation(&0);
+        client.add_confirmation(&0);
+        let swap = client.get_swap(&0).unwrap();
+        assert_eq!(swap.status, SwapStatus::Pending);
+        assert_eq!(swap.confirmations, 2);
+
+        client.add_confirmation(&0);
+        let swap = client.get_swap(&0).unwrap();
+        assert_eq!(swap.status, SwapStatus::Completed);
+        assert_eq!(swap.confirmations, 3);
+
+        let (_, topics, _) = env.events().all().last().unwrap();
+        assert_eq!(
+            topics,
+            (symbol_short!("confirmed"), EVENT_VERSION).into_val(&env)
+        );
+
+        // Completed swaps take no further confirmations
+        assert_eq!(client.try_add_confirmation(&0), Err(Ok(Error::NotPending)));
+        assert_eq!(
+            client.try_add_confirmation(&9),
+            Err(Ok(Error::SwapNotFound))
+        );
+    }
+
+    #[test]
+    fn test_swap_by_id() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &usdc, &xlm, 50, 500);
+        let index = record(&client, &user, &xlm, &usdc, 100, 1000);
+        let swap_id = client.get_swap(&index).unwrap().swap_id;
+
+        // The id can be reproduced off-chain from the swap contents
+        let payload = (
+            user.clone(),
+            xlm.clone(),
+            usdc.clone(),
+            100_i128,
+            1000_u64,
+            1_u64,
+        )
+            .to_xdr(&env);
+        let expected: BytesN<32> = env.crypto().sha256(&payload).into();
+        assert_eq!(swap_id, expected);
+
+        let fetched = client.get_swap_by_id(&swap_id).unwrap();
+        assert_eq!(fetched.amount, 100);
+        assert_eq!(fetched.swap_id, swap_id);
+
+        let unknown = BytesN::from_array(&env, &[7; 32]);
+        assert_eq!(client.get_swap_by_id(&unknown), None);
+    }
+
+    #[test]
+    fn test_swaps_for_users_merged() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let carol = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &alice, &xlm, &usdc, 1, 1000);
+        record(&client, &bob, &xlm, &usdc, 2, 2000);
+        record(&client, &carol, &xlm, &usdc, 3, 3000);
+        record(&client, &alice, &xlm, &usdc, 4, 4000);
+        record(&client, &carol, &xlm, &usdc, 5, 5000);
+        record(&client, &bob, &xlm, &usdc, 6, 6000);
+
+        let group = vec![&env, alice.clone(), bob.clone(), alice.clone()];
+        let swaps = client.get_swaps_for_users(&group, &10);
+        let mut amounts = Vec::<i128>::new(&env);
+        for swap in swaps.iter() {
+            amounts.push_back(swap.amount);
+        }
+        assert_eq!(amounts, vec![&env, 6, 4, 2, 1]);
+
+        let swaps = client.get_swaps_for_users(&group, &2);
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps.get(1).unwrap().user, alice);
+    }
+
+    #[test]
+    fn test_per_asset_minimums() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let eurc = String::from_str(&env, "EURC");
+
+        client.set_min_amount(&500);
+        client.set_asset_min(&usdc, &1);
+        client.set_asset_min(&xlm, &1_000_000);
+
+        // USDC floor of 1 overrides the higher global minimum
+        record(&client, &user, &usdc, &xlm, 1, 1000);
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &usdc, &xlm, 0, 1001)),
+            Err(Ok(Error::AmountTooSmall))
+        );
+
+        // XLM floor of 1_000_000
+        record(&client, &user, &xlm, &usdc, 1_000_000, 2000);
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 999_999, 2001)),
+            Err(Ok(Error::AmountTooSmall))
+        );
+
+        // Assets without a floor fall back to the global minimum
+        assert_eq!(client.get_asset_min(&eurc), 500);
+        record(&client, &user, &eurc, &xlm, 500, 3000);
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &eurc, &xlm, 499, 3001)),
+            Err(Ok(Error::AmountTooSmall))
+        );
+
+        assert_eq!(client.get_swap_count(), 3);
+    }
+
+    #[test]
+    fn test_recent_avg_rate() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        assert_eq!(client.get_recent_avg_rate(&xlm, &usdc, &2), None);
+
+        // Rates 0.10, 0.12, 0.20 and 0.30 USDC per XLM
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 10,
+                ..swap_input(&user, &xlm, &usdc, 100, 1000)
+            },
+        );
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 12,
+                ..swap_input(&user, &xlm, &usdc, 100, 2000)
+            },
+        );
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 20,
+                ..swap_input(&user, &xlm, &usdc, 100, 3000)
+            },
+        );
+        // Reverse pair is ignored
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 100,
+                ..swap_input(&user, &usdc, &xlm, 10, 3500)
+            },
+        );
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 30,
+                ..swap_input(&user, &xlm, &usdc, 100, 4000)
+            },
+        );
+
+        // Average of the last two XLM->USDC rates (0.30 and 0.20)
+        assert_eq!(client.get_recent_avg_rate(&xlm, &usdc, &2), Some(2_500_000));
+        // Asking for more than exist averages all of them
+        assert_eq!(
+            client.get_recent_avg_rate(&xlm, &usdc, &10),
+            Some(1_800_000)
+        );
+    }
+
+    #[test]
+    fn test_flag_swap() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &usdc, &xlm, 200, 2000);
+        let before = client.get_swap(&1).unwrap();
+
+        let reason = symbol_short!("sanctions");
+        client.flag_swap(&1, &reason);
+
+        let (_, topics, _) = env.events().all().last().unwrap();
+        assert_eq!(
+            topics,
+            (symbol_short!("flagged"), EVENT_VERSION).into_val(&env)
+        );
+
+        assert_eq!(client.get_swap_flag(&1), Some(reason.clone()));
+        assert_eq!(client.get_swap_flag(&0), None);
+
+        let flagged = client.get_flagged_swaps(&10);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged.get(0).unwrap(), before);
+
+        // The record itself is untouched and still served normally
+        assert_eq!(client.get_swap(&1).unwrap(), before);
+        assert_eq!(client.get_recent_swaps(&1).get(0).unwrap(), before);
+
+        // Re-flagging updates the reason without duplicating the entry
+        client.flag_swap(&1, &symbol_short!("review"));
+        assert_eq!(client.get_flagged_swaps(&10).len(), 1);
+        assert_eq!(client.get_swap_flag(&1), Some(symbol_short!("review")));
+
+        assert_eq!(
+            client.try_flag_swap(&5, &reason),
+            Err(Ok(Error::SwapNotFound))
+        );
+    }
+
+    #[test]
+    fn test_daily_count_series() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        let day = 86_400_u64;
+        record(&client, &user, &xlm, &usdc, 100, 10 * day);
+        record(&client, &user, &xlm, &usdc, 100, 10 * day + 500);
+        record(&client, &user, &xlm, &usdc, 100, 11 * day + 1);
+        record(&client, &user, &xlm, &usdc, 100, 12 * day + 2);
+        record(&client, &user, &xlm, &usdc, 100, 12 * day + 3);
+        record(&client, &user, &xlm, &usdc, 100, 12 * day + 4);
+
+        assert_eq!(
+            client.get_daily_count_series(&9, &13),
+            vec![&env, 0, 2, 1, 3, 0]
+        );
+        assert!(client.get_daily_count_series(&12, &10).is_empty());
+
+        // Oversized ranges are clamped
+        assert_eq!(client.get_daily_count_series(&0, &10_000).len(), 366);
+    }
+
+    #[test]
+    fn test_asset_flow_counts() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let eurc = String::from_str(&env, "EURC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &eurc, &xlm, 100, 2000);
+        record(&client, &user, &xlm, &eurc, 100, 3000);
+
+        assert_eq!(client.get_asset_flow_counts(&xlm), (2, 1));
+        assert_eq!(client.get_asset_flow_counts(&usdc), (0, 1));
+        assert_eq!(client.get_asset_flow_counts(&eurc), (1, 1));
+    }
+
+    #[test]
+    fn test_block_address() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+
+        client.block_address(&user);
+        assert!(client.is_blocked(&user));
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 200, 2000)),
+            Err(Ok(Error::AddressBlocked))
+        );
+
+        // Existing records stay readable
+        assert_eq!(client.get_user_swap_count(&user), 1);
+        assert_eq!(client.get_swap(&0).unwrap().user, user);
+
+        client.unblock_address(&user);
+        assert!(!client.is_blocked(&user));
+        record(&client, &user, &xlm, &usdc, 200, 2000);
+        assert_eq!(client.get_user_swap_count(&user), 2);
+    }
+
+    /// Returns the data of the most recent event as a vector of values.
+    fn last_event_data(env: &Env) -> Vec<Val> {
+        let (_, _, data) = env.events().all().last().unwrap();
+        data.into_val(env)
+    }
+
+    /// Returns the topics and data of the latest `swap` event.
+    fn last_swap_event(env: &Env) -> (Vec<Val>, Val) {
+        let (_, topics, data) = env
+            .events()
+            .all()
+            .iter()
+            .filter(|(_, topics, _)| {
+                topics
+                    .get(0)
+                    .and_then(|topic| Symbol::try_from_val(env, &topic).ok())
+                    == Some(symbol_short!("swap"))
+            })
+            .last()
+            .unwrap();
+        (topics, data)
+    }
+
+    /// Counts emitted events whose first topic is `name`.
+    fn count_events(env: &Env, name: Symbol) -> u32 {
+        let mut count = 0;
+        for (_, topics, _) in env.events().all().iter() {
+            let first: Option<Symbol> = topics
+                .get(0)
+                .and_then(|topic| Symbol::try_from_val(env, &topic).ok());
+            if first == Some(name.clone()) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_milestone_events() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_milestone_interval(&2);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        assert_eq!(count_events(&env, symbol_short!("milestone")), 0);
+
+        record(&client, &user, &xlm, &usdc, 100, 2000);
+        assert_eq!(count_events(&env, symbol_short!("milestone")), 1);
+        let (_, _, data) = env.events().all().last().unwrap();
+        assert_eq!(u64::try_from_val(&env, &data).unwrap(), 2);
+
+        // The third swap is not a multiple of the interval
+        record(&client, &user, &xlm, &usdc, 100, 3000);
+        assert_eq!(count_events(&env, symbol_short!("milestone")), 1);
+    }
+
+    #[test]
+    fn test_approx_median_amount() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert_eq!(client.get_approx_median_amount(), 0);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        // Mostly mid-sized swaps plus two huge outliers
+        let amounts = [20, 35, 50, 60, 75, 90, 400, 1_000_000_000, 5_000_000_000];
+        for (i, amount) in amounts.iter().enumerate() {
+            record(&client, &user, &xlm, &usdc, *amount, 1000 + i as u64);
+        }
+
+        let mean = client.get_total_volume() / amounts.len() as i128;
+        assert!(mean > 600_000_000);
+
+        // The median sits in the [10, 100) bucket, whose midpoint is 55
+        assert_eq!(client.get_approx_median_amount(), 55);
+    }
+
+    #[test]
+    fn test_records_ledger_sequence() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        env.ledger().set_sequence_number(42);
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        env.ledger().set_sequence_number(50);
+        record(&client, &user, &xlm, &usdc, 100, 2000);
+
+        assert_eq!(client.get_swap(&0).unwrap().ledger_seq, 42);
+        assert_eq!(client.get_swap(&1).unwrap().ledger_seq, 50);
+
+        let payload = last_event_data(&env);
+        assert_eq!(
+            u32::try_from_val(&env, &payload.get(5).unwrap()).unwrap(),
+            50
+        );
+    }
+
+    #[test]
+    fn test_user_current_streak() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let gappy = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        let day = 86_400_u64;
+        record(&client, &user, &xlm, &usdc, 100, 5 * day + 10);
+        record(&client, &user, &xlm, &usdc, 100, 6 * day + 10);
+        record(&client, &user, &xlm, &usdc, 100, 7 * day + 10);
+        record(&client, &user, &xlm, &usdc, 100, 7 * day + 20);
+
+        record(&client, &gappy, &xlm, &usdc, 100, 4 * day);
+        record(&client, &gappy, &xlm, &usdc, 100, 6 * day);
+        record(&client, &gappy, &xlm, &usdc, 100, 7 * day);
+
+        env.ledger().set_timestamp(7 * day + 3_600);
+        assert_eq!(client.get_user_current_streak(&user), 3);
+        // The missing day 5 breaks the streak
+        assert_eq!(client.get_user_current_streak(&gappy), 2);
+
+        // No swap yet today means no current streak
+        env.ledger().set_timestamp(8 * day + 1);
+        assert_eq!(client.get_user_current_streak(&user), 0);
+    }
+
+    #[test]
+    fn test_tick_direction() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 10,
+                ..swap_input(&user, &xlm, &usdc, 100, 1000)
+            },
+        );
+        assert_eq!(
+            client.get_last_tick_direction(&xlm, &usdc),
+            TickDirection::Flat
+        );
+
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 12,
+                ..swap_input(&user, &xlm, &usdc, 100, 2000)
+            },
+        );
+        assert_eq!(
+            client.get_last_tick_direction(&xlm, &usdc),
+            TickDirection::Up
+        );
+
+        let payload = last_event_data(&env);
+        assert_eq!(
+            TickDirection::try_from_val(&env, &payload.get(6).unwrap()).unwrap(),
+            TickDirection::Up
+        );
+
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 11,
+                ..swap_input(&user, &xlm, &usdc, 100, 3000)
+            },
+        );
+        assert_eq!(
+            client.get_last_tick_direction(&xlm, &usdc),
+            TickDirection::Down
+        );
+
+        // Other pairs are tracked independently
+        assert_eq!(
+            client.get_last_tick_direction(&usdc, &xlm),
+            TickDirection::Flat
+        );
+    }
+
+    /// Builds a batch of `n` distinct 1:1 swaps under the default category.
+    fn batch_of(env: &Env, user: &Address, n: u32) -> Vec<SwapInput> {
+        let mut batch = vec![env];
+        for i in 0..n {
+            batch.push_back(SwapInput {
+                user: user.clone(),
+                from_asset: String::from_str(env, "XLM"),
+                to_asset: String::from_str(env, "USDC"),
+                amount: 100 + i as i128,
+                to_amount: 100 + i as i128,
+                timestamp: 1000 + i as u64,
+                category: CATEGORY,
+                parent_id: None,
+                app_id: APP,
+                deadline_ts: 0,
+            });
+        }
+        batch
+    }
+
+    #[test]
+    fn test_record_swaps_batch() {
+        let env = Env::default();
+        let client = setup(&env);
+        let user = Address::generate(&env);
+
+        assert_eq!(client.get_max_batch_size(), 50);
+        client.set_max_batch_size(&3);
+
+        let indices = client.record_swaps_batch(&batch_of(&env, &user, 3));
+        assert_eq!(indices, vec![&env, 0, 1, 2]);
+        assert_eq!(client.get_swap_count(), 3);
+        assert_eq!(client.get_swap(&2).unwrap().amount, 102);
+
+        // One over the limit is rejected without recording anything
+        assert_eq!(
+            client.try_record_swaps_batch(&batch_of(&env, &user, 4)),
+            Err(Ok(Error::BatchTooLarge))
+        );
+        assert_eq!(client.get_swap_count(), 3);
+        assert_eq!(client.get_user_swap_count(&user), 3);
+    }
+
+    #[test]
+    fn test_total_fee_value() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_fee_bps(&100);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 10_000, 1000);
+        record(&client, &user, &usdc, &xlm, 5_000, 2000);
+        assert_eq!(client.get_asset_fees(&xlm), 100);
+        assert_eq!(client.get_asset_fees(&usdc), 50);
+        assert_eq!(
+            client.get_asset_list(),
+            vec![&env, xlm.clone(), usdc.clone()]
+        );
+
+        // Unpriced assets contribute nothing
+        client.set_asset_price(&usdc, &10_000_000);
+        assert_eq!(client.get_total_fee_value(), 50);
+
+        // 100 XLM fee at 0.1 + 50 USDC fee at 1.0
+        client.set_asset_price(&xlm, &1_000_000);
+        assert_eq!(client.get_total_fee_value(), 60);
+
+        assert_eq!(client.try_set_fee_bps(&10_001), Err(Ok(Error::InvalidFee)));
+    }
+
+    #[test]
+    fn test_total_fee_value_saturates() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_fee_bps(&100);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        record(&client, &user, &xlm, &usdc, MAX_SWAP_AMOUNT, 1000);
+        record(&client, &user, &usdc, &xlm, MAX_SWAP_AMOUNT, 2000);
+
+        // Each fee total times its price alone exceeds i128::MAX
+        client.set_asset_price(&xlm, &30_000_000_000);
+        client.set_asset_price(&usdc, &30_000_000_000);
+        assert_eq!(client.get_total_fee_value(), i128::MAX);
+    }
+
+    #[test]
+    fn test_swaps_by_parent() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let parent = BytesN::from_array(&env, &[1; 32]);
+        let other_parent = BytesN::from_array(&env, &[2; 32]);
+
+        // Second partial carries an earlier timestamp than the first
+        let fill = |amount, timestamp, parent: &BytesN<32>| SwapInput {
+            parent_id: Some(parent.clone().into()),
+            ..swap_input(&user, &xlm, &usdc, amount, timestamp)
+        };
+        submit(&client, &fill(300, 3000, &parent));
+        record(&client, &user, &xlm, &usdc, 999, 2500);
+        submit(&client, &fill(100, 1000, &parent));
+        submit(&client, &fill(50, 2000, &other_parent));
+
+        let fills = client.get_swaps_by_parent(&parent);
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills.get(0).unwrap().amount, 100);
+        assert_eq!(fills.get(1).unwrap().amount, 300);
+        assert_eq!(fills.get(0).unwrap().parent_id, Some(parent.clone().into()));
+
+        assert_eq!(client.get_swaps_by_parent(&other_parent).len(), 1);
+        assert_eq!(client.get_swap(&1).unwrap().parent_id, None);
+    }
+
+    #[test]
+    fn test_swaps_excluding_asset() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let eurc = String::from_str(&env, "EURC");
+
+        record(&client, &user, &usdc, &eurc, 100, 1000);
+        record(&client, &user, &xlm, &usdc, 200, 2000);
+        record(&client, &user, &eurc, &usdc, 300, 3000);
+        record(&client, &user, &usdc, &xlm, 400, 4000);
+        record(&client, &user, &eurc, &usdc, 500, 5000);
+
+        let swaps = client.get_swaps_excluding_asset(&xlm, &10);
+        assert_eq!(swaps.len(), 3);
+        assert_eq!(swaps.get(0).unwrap().amount, 500);
+        assert_eq!(swaps.get(1).unwrap().amount, 300);
+        assert_eq!(swaps.get(2).unwrap().amount, 100);
+
+        let capped = client.get_swaps_excluding_asset(&xlm, &2);
+        assert_eq!(capped.len(), 2);
+        assert_eq!(capped.get(1).unwrap().amount, 300);
+    }
+
+    #[test]
+    fn test_daily_candle() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        for (rate, timestamp) in [(10, 1000), (15, 2000), (8, 3000)] {
+            submit(
+                &client,
+                &SwapInput {
+                    to_amount: 100 * rate,
+                    ..swap_input(&user, &xlm, &usdc, 100, timestamp)
+                },
+            );
+        }
+
+        let (open, high, low, close) = client.get_candle(&xlm, &usdc, &0).unwrap();
+        assert_eq!(open, 10 * DEFAULT_RATE_SCALE);
+        assert_eq!(high, 15 * DEFAULT_RATE_SCALE);
+        assert_eq!(low, 8 * DEFAULT_RATE_SCALE);
+        assert_eq!(close, 8 * DEFAULT_RATE_SCALE);
+
+        assert_eq!(client.get_candle(&xlm, &usdc, &1), None);
+        assert_eq!(client.get_candle(&usdc, &xlm, &0), None);
+    }
+
+    #[test]
+    fn test_record_swap_returns_index() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        for expected in 0..3_u64 {
+            let index = client.record_swap(
+                &user,
+                &xlm,
+                &usdc,
+                &100,
+                &100,
+                &(1000 + expected),
+                &CATEGORY,
+                &None,
+                &APP,
+                &0,
+            );
+            assert_eq!(index, expected);
+            assert_eq!(client.get_swap(&index).unwrap().timestamp, 1000 + expected);
+        }
+    }
+
+    #[test]
+    fn test_asset_summaries() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 600,
+                ..swap_input(&user, &xlm, &usdc, 200, 2000)
+            },
+        );
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 25,
+                ..swap_input(&user, &usdc, &xlm, 50, 3000)
+            },
+        );
+
+        let summaries = client.get_asset_summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(
+            summaries.get(0).unwrap(),
+            AssetSummary {
+                asset: xlm.clone(),
+                volume: 300,
+                count: 3,
+                last_rate: 3 * DEFAULT_RATE_SCALE,
+            }
+        );
+        assert_eq!(
+            summaries.get(1).unwrap(),
+            AssetSummary {
+                asset: usdc.clone(),
+                volume: 50,
+                count: 3,
+                last_rate: DEFAULT_RATE_SCALE / 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_renounce_admin() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        client.renounce_admin();
+        assert_eq!(client.get_admin(), None);
+
+        assert_eq!(client.try_pause(), Err(Ok(Error::NoAdmin)));
+        assert_eq!(
+            client.try_upgrade(&BytesN::from_array(&env, &[7; 32])),
+            Err(Ok(Error::NoAdmin))
+        );
+        assert_eq!(client.try_set_fee_bps(&30), Err(Ok(Error::NoAdmin)));
+        assert_eq!(client.try_renounce_admin(), Err(Ok(Error::NoAdmin)));
+
+        // Nobody can claim the contract by initializing it again
+        let squatter = Address::generate(&env);
+        assert_eq!(
+            client.try_initialize(&squatter),
+            Err(Ok(Error::AlreadyInitialized))
+        );
+        assert!(!client.is_paused());
+    }
+
+    #[test]
+    fn test_div_round_modes() {
+        assert_eq!(div_round(5, 2, RoundingMode::Floor), 2);
+        assert_eq!(div_round(5, 2, RoundingMode::Ceil), 3);
+        assert_eq!(div_round(5, 2, RoundingMode::Nearest), 3);
+
+        assert_eq!(div_round(4, 3, RoundingMode::Ceil), 2);
+        assert_eq!(div_round(4, 3, RoundingMode::Nearest), 1);
+        assert_eq!(div_round(6, 2, RoundingMode::Ceil), 3);
+    }
+
+    #[test]
+    fn test_rounding_mode_applies_to_rates() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert_eq!(client.get_rounding_mode(), RoundingMode::Floor);
+
+        // 2 / 3 scaled by 10^7 is 6_666_666.67
+        client.set_rounding_mode(&RoundingMode::Nearest);
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 2,
+                ..swap_input(&user, &xlm, &usdc, 3, 1000)
+            },
+        );
+
+        let (open, _, _, _) = client.get_candle(&xlm, &usdc, &0).unwrap();
+        assert_eq!(open, 6_666_667);
+        assert_eq!(client.get_recent_avg_rate(&xlm, &usdc, &1), Some(6_666_667));
+    }
+
+    #[test]
+    fn test_day_run_rate_projection() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        // Day 2, 06:00 and 09:00
+        let day_start = 2 * 86_400;
+        record(&client, &user, &xlm, &usdc, 300, day_start + 21_600);
+        record(&client, &user, &xlm, &usdc, 700, day_start + 32_400);
+
+        // Halfway through the day, 1000 projects to 2000
+        env.ledger().with_mut(|l| l.timestamp = day_start + 43_200);
+        assert_eq!(client.get_day_run_rate_projection(&2), 2000);
+
+        // Other days are not extrapolated
+        env.ledger().with_mut(|l| l.timestamp = 3 * 86_400);
+        assert_eq!(client.get_day_run_rate_projection(&2), 1000);
+        assert_eq!(client.get_day_run_rate_projection(&3), 0);
+    }
+
+    #[test]
+    fn test_swaps_in_amount_range() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 10, 1000);
+        record(&client, &user, &xlm, &usdc, 100, 2000);
+        record(&client, &user, &xlm, &usdc, 1000, 3000);
+
+        let swaps = client.get_swaps_in_amount_range(&50, &500, &10);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps.get(0).unwrap().amount, 100);
+
+        // Bounds are inclusive
+        assert_eq!(client.get_swaps_in_amount_range(&10, &100, &10).len(), 2);
+        assert_eq!(client.get_swaps_in_amount_range(&500, &50, &10).len(), 0);
+    }
+
+    #[test]
+    fn test_idempotency_key_expires() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let swap = swap_input(&user, &xlm, &usdc, 100, 1000);
+        client.set_idempotency_ttl(&100);
+
+        env.ledger().with_mut(|l| l.sequence_number = 10);
+        submit(&client, &swap);
+        assert_eq!(try_submit(&client, &swap), Err(Ok(Error::DuplicateSwap)));
+
+        // A different timestamp is a different key
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1001));
+
+        env.ledger().with_mut(|l| l.sequence_number = 110);
+        assert_eq!(try_submit(&client, &swap), Err(Ok(Error::DuplicateSwap)));
+
+        // Once the key expires the same swap is accepted again
+        env.ledger().with_mut(|l| l.sequence_number = 111);
+        submit(&client, &swap);
+        assert_eq!(client.get_swap_count(), 3);
+    }
+
+    #[test]
+    fn test_next_allowed_swap() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        client.set_min_swap_interval(&60);
+        assert_eq!(client.next_allowed_swap(&user), 0);
+
+        env.ledger().with_mut(|l| l.timestamp = 1000);
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        assert_eq!(client.next_allowed_swap(&user), 1060);
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1030)),
+            Err(Ok(Error::RateLimited))
+        );
+
+        env.ledger().with_mut(|l| l.timestamp = 1060);
+        assert_eq!(client.next_allowed_swap(&user), 0);
+        record(&client, &user, &xlm, &usdc, 200, 1060);
+    }
+
+    #[test]
+    fn test_merge_asset() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_fee_bps(&100);
+
+        let user = Address::generate(&env);
+        let old = String::from_str(&env, "OLD");
+        let new = String::from_str(&env, "NEW");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &old, &usdc, 1000, 1000);
+        record(&client, &user, &usdc, &old, 300, 2000);
+        record(&client, &user, &new, &usdc, 500, 3000);
+
+        client.merge_asset(&old, &new);
+
+        assert_eq!(client.get_asset_flow_counts(&new), (2, 1));
+        assert_eq!(client.get_asset_flow_counts(&old), (0, 0));
+        assert_eq!(client.get_asset_fees(&new), 15);
+        assert_eq!(client.get_asset_fees(&old), 0);
+
+        let summaries = client.get_asset_summaries();
+        assert_eq!(summaries.len(), 2);
+        let merged = summaries.get(1).unwrap();
+        assert_eq!(merged.asset, new);
+        assert_eq!(merged.volume, 1500);
+        assert_eq!(merged.count, 3);
+
+        // Raw records keep the original code
+        assert_eq!(client.get_swap(&0).unwrap().from_asset, old);
+    }
+
+    #[test]
+    fn test_merge_asset_then_rebuild() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_fee_bps(&100);
+
+        let user = Address::generate(&env);
+        let old = String::from_str(&env, "OLD");
+        let new = String::from_str(&env, "NEW");
+        let usdc = String::from_str(&env, "USDC");
+
+        // A pure rename: no record ever uses the new code
+        record(&client, &user, &old, &usdc, 1000, 1000);
+        record(&client, &user, &usdc, &old, 300, 2000);
+        client.merge_asset(&old, &new);
+        assert_eq!(client.get_asset_flow_counts(&new), (1, 1));
+
+        client.rebuild_aggregates();
+        assert_eq!(client.get_asset_flow_counts(&old), (1, 1));
+        assert_eq!(client.get_asset_flow_counts(&new), (0, 0));
+        assert_eq!(client.get_asset_fees(&old), 10);
+        assert_eq!(client.get_asset_fees(&new), 0);
+        assert!(client.verify_volume(&old));
+        assert!(client.verify_volume(&new));
+        assert!(client.get_asset_list().contains(&old));
+    }
+
+    #[test]
+    fn test_verify_volume() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &xlm, &usdc, 200, 2000);
+        record(&client, &user, &usdc, &xlm, 50, 3000);
+        assert!(client.verify_volume(&xlm));
+        assert!(client.verify_volume(&usdc));
+
+        env.as_contract(&client.address, || {
+            env.storage()
+                .persistent()
+                .set(&DataKey::AssetVolume(xlm.clone()), &999_i128);
+        });
+        assert!(!client.verify_volume(&xlm));
+        assert!(client.verify_volume(&usdc));
+
+        // A swap recorded without aggregation is seen by the scan only
+        client.set_aggregation_enabled(&false);
+        record(&client, &user, &usdc, &xlm, 25, 4000);
+        assert!(!client.verify_volume(&usdc));
+    }
+
+    #[test]
+    fn test_rate_scale() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert_eq!(client.get_rate_scale(), 10_000_000);
+
+        assert_eq!(
+            client.try_set_rate_scale(&0),
+            Err(Ok(Error::InvalidRateScale))
+        );
+        client.set_rate_scale(&1_000);
+        assert_eq!(client.get_rate_scale(), 1_000);
+
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 250,
+                ..swap_input(&user, &xlm, &usdc, 100, 1000)
+            },
+        );
+        assert_eq!(client.get_recent_avg_rate(&xlm, &usdc, &1), Some(2_500));
+    }
+
+    #[test]
+    fn test_user_reputation() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_confirmation_threshold(&1);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert_eq!(client.get_user_reputation(&user), 50);
+
+        for i in 0..4 {
+            record(&client, &user, &xlm, &usdc, 100, 1000 + i);
+        }
+        // Pending swaps don't count yet
+        assert_eq!(client.get_user_reputation(&user), 50);
+
+        client.add_confirmation(&0);
+        client.add_confirmation(&1);
+        client.add_confirmation(&2);
+        client.fail_swap(&3);
+        assert_eq!(client.get_swap(&3).unwrap().status, SwapStatus::Failed);
+        assert_eq!(client.try_fail_swap(&3), Err(Ok(Error::NotPending)));
+        assert_eq!(client.get_user_reputation(&user), 75);
+
+        // Rebuilding derives the same score from stored statuses
+        client.rebuild_aggregates();
+        assert_eq!(client.get_user_reputation(&user), 75);
+    }
+
+    #[test]
+    fn test_active_days() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        // Day 5 is recorded before day 2 and twice
+        record(&client, &user, &xlm, &usdc, 100, 5 * 86_400 + 10);
+        record(&client, &user, &xlm, &usdc, 100, 2 * 86_400 + 10);
+        record(&client, &user, &xlm, &usdc, 100, 5 * 86_400 + 20);
+
+        assert_eq!(client.get_active_days(), vec![&env, 2, 5]);
+
+        client.rebuild_aggregates();
+        assert_eq!(client.get_active_days(), vec![&env, 2, 5]);
+    }
+
+    #[test]
+    fn test_ledger_throttle() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_max_swaps_per_ledger(&2);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        env.ledger().with_mut(|l| l.sequence_number = 10);
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &xlm, &usdc, 200, 2000);
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 300, 3000)),
+            Err(Ok(Error::LedgerThrottled))
+        );
+        assert_eq!(client.get_swap_count(), 2);
+
+        // The next ledger starts a fresh count
+        env.ledger().with_mut(|l| l.sequence_number = 11);
+        record(&client, &user, &xlm, &usdc, 300, 3000);
+        assert_eq!(client.get_swap_count(), 3);
+    }
+
+    #[test]
+    fn test_events_carry_version() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+
+        let (topics, _) = last_swap_event(&env);
+        assert_eq!(topics.len(), 3);
+        let version = u32::try_from_val(&env, &topics.get(1).unwrap()).unwrap();
+        assert_eq!(version, EVENT_VERSION);
+    }
+
+    #[test]
+    fn test_swaps_for_asset() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let eurc = String::from_str(&env, "EURC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &usdc, &eurc, 200, 2000);
+        record(&client, &user, &usdc, &xlm, 300, 3000);
+
+        let swaps = client.get_swaps_for_asset(&xlm, &10);
+        assert_eq!(swaps.len(), 2);
+        let (newest, newest_sold) = swaps.get(0).unwrap();
+        assert_eq!(newest.amount, 300);
+        assert!(!newest_sold);
+        let (oldest, oldest_sold) = swaps.get(1).unwrap();
+        assert_eq!(oldest.amount, 100);
+        assert!(oldest_sold);
+    }
+
+    #[test]
+    fn test_import_swap() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let historical = |amount: i128, timestamp: u64| SwapRecord {
+            user: user.clone(),
+            from_asset: xlm.clone(),
+            to_asset: usdc.clone(),
+            amount,
+            to_amount: amount,
+            timestamp,
+            category: CATEGORY,
+            status: SwapStatus::Completed,
+            confirmations: 0,
+            swap_id: BytesN::from_array(&env, &[timestamp as u8; 32]),
+            ledger_seq: 7,
+            parent_id: None,
+            app_id: APP,
+            deadline_ts: 0,
+            quote_value: 0,
+            fee: 0,
+            prev_hash: BytesN::from_array(&env, &[0; 32]),
+        };
+
+        assert_eq!(
+            client.try_import_swap(&0, &historical(100, 1)),
+            Err(Ok(Error::ImportDisabled))
+        );
+
+        client.set_import_mode(&true);
+        client.import_swap(&5, &historical(500, 5));
+        client.import_swap(&0, &historical(100, 1));
+        assert_eq!(client.get_swap_count(), 6);
+
+        assert_eq!(client.get_swap(&0).unwrap().amount, 100);
+        assert_eq!(client.get_swap(&5).unwrap().ledger_seq, 7);
+        for gap in 1..5 {
+            assert_eq!(client.get_swap(&gap), None);
+        }
+        assert_eq!(client.get_user_swap_indices(&user), vec![&env, 0, 5]);
+        assert_eq!(client.get_total_volume(), 600);
+
+        assert_eq!(
+            client.try_import_swap(&5, &historical(1, 9)),
+            Err(Ok(Error::IndexTaken))
+        );
+        assert_eq!(
+            client.try_import_swap(&u64::MAX, &historical(1, 9)),
+            Err(Ok(Error::InvalidRange))
+        );
+        assert_eq!(client.get_swap_count(), 6);
+    }
+
+    #[test]
+    fn test_top_swaps_in_range() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 9000, 500);
+        record(&client, &user, &xlm, &usdc, 300, 1000);
+        record(&client, &user, &xlm, &usdc, 700, 1500);
+        record(&client, &user, &xlm, &usdc, 100, 2000);
+        record(&client, &user, &xlm, &usdc, 500, 2500);
+        record(&client, &user, &xlm, &usdc, 8000, 3500);
+
+        let top = client.get_top_swaps_in_range(&1000, &3000, &2);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top.get(0).unwrap().amount, 700);
+        assert_eq!(top.get(1).unwrap().amount, 500);
+
+        assert_eq!(client.get_top_swaps_in_range(&1000, &3000, &10).len(), 4);
+        assert_eq!(client.get_top_swaps_in_range(&3000, &1000, &2).len(), 0);
+    }
+
+    #[test]
+    fn test_user_milestone_event() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_user_milestone(&1000);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let milestone = Symbol::new(&env, "user_milestone");
+
+        record(&client, &user, &xlm, &usdc, 600, 1000);
+        assert_eq!(count_events(&env, milestone.clone()), 0);
+
+        record(&client, &user, &xlm, &usdc, 600, 2000);
+        let data = last_event_data(&env);
+        assert_eq!(
+            Address::try_from_val(&env, &data.get(0).unwrap()).unwrap(),
+            user
+        );
+        assert_eq!(
+            i128::try_from_val(&env, &data.get(1).unwrap()).unwrap(),
+            1200
+        );
+
+        record(&client, &user, &xlm, &usdc, 600, 3000);
+        assert_eq!(count_events(&env, milestone), 1);
+    }
+
+    #[test]
+    fn test_undecodable_aggregates_read_as_default() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        env.as_contract(&client.address, || {
+            let storage = env.storage().persistent();
+            storage.set(&DataKey::TotalVolume, &symbol_short!("bad"));
+            storage.set(&DataKey::UserVolume(user.clone()), &xlm);
+            storage.set(&DataKey::AmountHistogram, &vec![&env, 1_u64]);
+        });
+        assert_eq!(client.get_total_volume(), 0);
+        assert_eq!(client.get_user_volume(&user), 0);
+        assert_eq!(client.get_approx_median_amount(), 0);
+
+        // Recording overwrites the incompatible values
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        assert_eq!(client.get_total_volume(), 100);
+        assert_eq!(client.get_user_volume(&user), 100);
+    }
+
+    #[test]
+    fn test_rate_spread() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert_eq!(client.get_rate_spread(&xlm, &usdc), None);
+
+        for (rate, timestamp) in [(10, 1000), (15, 2000), (8, 3000)] {
+            submit(
+                &client,
+                &SwapInput {
+                    to_amount: 100 * rate,
+                    ..swap_input(&user, &xlm, &usdc, 100, timestamp)
+                },
+            );
+        }
+
+        assert_eq!(
+            client.get_rate_spread(&xlm, &usdc),
+            Some((8 * DEFAULT_RATE_SCALE, 15 * DEFAULT_RATE_SCALE))
+        );
+        assert_eq!(client.get_rate_spread(&usdc, &xlm), None);
+    }
+
+    #[test]
+    fn test_app_volume() {
+        let env = Env::default();
+        let client = setup(&env);
+        let mobile = symbol_short!("mobile");
+        let spoofed = symbol_short!("spoofed");
+        client.set_app_allowed(&mobile, &true);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &xlm, &usdc, 200, 2000);
+        submit(
+            &client,
+            &SwapInput {
+                app_id: mobile.clone(),
+                ..swap_input(&user, &xlm, &usdc, 50, 3000)
+            },
+        );
+
+        assert_eq!(client.get_app_volume(&APP), 300);
+        assert_eq!(client.get_app_count(&APP), 2);
+        assert_eq!(client.get_app_volume(&mobile), 50);
+        assert_eq!(client.get_app_count(&mobile), 1);
+        assert_eq!(client.get_swap(&2).unwrap().app_id, mobile);
+
+        assert_eq!(
+            try_submit(
+                &client,
+                &SwapInput {
+                    app_id: spoofed.clone(),
+                    ..swap_input(&user, &xlm, &usdc, 50, 4000)
+                },
+            ),
+            Err(Ok(Error::AppNotAllowed))
+        );
+        assert_eq!(client.get_app_volume(&spoofed), 0);
+    }
+
+    #[test]
+    fn test_swaps_last_seconds() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &xlm, &usdc, 200, 9500);
+        record(&client, &user, &xlm, &usdc, 300, 9900);
+        record(&client, &user, &xlm, &usdc, 400, 10_500);
+
+        env.ledger().with_mut(|l| l.timestamp = 10_000);
+        let swaps = client.get_swaps_last_seconds(&600, &10);
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps.get(0).unwrap().amount, 300);
+        assert_eq!(swaps.get(1).unwrap().amount, 200);
+
+        assert_eq!(client.get_swaps_last_seconds(&600, &1).len(), 1);
+    }
+
+    #[test]
+    fn test_reset_asset_aggregates() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_fee_bps(&100);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let eurc = String::from_str(&env, "EURC");
+
+        record(&client, &user, &xlm, &usdc, 1000, 1000);
+        record(&client, &user, &eurc, &usdc, 500, 2000);
+
+        client.reset_asset_aggregates(&xlm);
+        assert_eq!(count_events(&env, Symbol::new(&env, "asset_reset")), 1);
+
+        assert_eq!(client.get_asset_flow_counts(&xlm), (0, 0));
+        assert_eq!(client.get_asset_fees(&xlm), 0);
+        assert_eq!(client.get_rate_spread(&xlm, &usdc), None);
+        let summaries = client.get_asset_summaries();
+        assert_eq!(summaries.get(0).unwrap().volume, 0);
+
+        assert_eq!(client.get_asset_flow_counts(&eurc), (1, 0));
+        assert_eq!(client.get_asset_fees(&eurc), 5);
+        assert!(client.get_rate_spread(&eurc, &usdc).is_some());
+        assert_eq!(client.get_swap_count(), 2);
+    }
+
+    #[test]
+    fn test_dup_filter() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let swap = swap_input(&user, &xlm, &usdc, 100, 1000);
+        client.set_idempotency_ttl(&100);
+
+        env.as_contract(&client.address, || {
+            let hash = BytesN::from_array(&env, &[9; 32]);
+            assert!(!dup_filter_check_and_set(&env, &hash));
+            assert!(dup_filter_check_and_set(&env, &hash));
+        });
+
+        submit(&client, &swap);
+        assert_eq!(try_submit(&client, &swap), Err(Ok(Error::DuplicateSwap)));
+
+        // A saturated filter only costs the exact lookup; new keys still pass
+        env.as_contract(&client.address, || {
+            let mut full = Bytes::new(&env);
+            for _ in 0..DUP_FILTER_BYTES {
+                full.push_back(0xff);
+            }
+            env.storage().persistent().set(&DataKey::DupFilter, &full);
+        });
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 2000));
+        assert_eq!(client.get_swap_count(), 2);
+    }
+
+    #[test]
+    fn test_recent_swaps_with_flags() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let reason = symbol_short!("wash");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &user, &xlm, &usdc, 200, 2000);
+        record(&client, &user, &xlm, &usdc, 300, 3000);
+        client.flag_swap(&1, &reason);
+
+        let swaps = client.get_recent_swaps_with_flags(&2);
+        assert_eq!(swaps.len(), 2);
+        let (newest, newest_flag) = swaps.get(0).unwrap();
+        assert_eq!(newest.amount, 300);
+        assert_eq!(newest_flag, None);
+        let (flagged, flag) = swaps.get(1).unwrap();
+        assert_eq!(flagged.amount, 200);
+        assert_eq!(flag, Some(reason));
+    }
+
+    #[test]
+    fn test_trader_concentration() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert_eq!(client.get_trader_concentration(), 0);
+
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &alice, &xlm, &usdc, 300, 1000);
+        record(&client, &alice, &xlm, &usdc, 700, 2000);
+        assert_eq!(client.get_trader_concentration(), 10_000);
+
+        record(&client, &bob, &xlm, &usdc, 1000, 3000);
+        assert_eq!(client.get_trader_concentration(), 5_000);
+
+        client.rebuild_aggregates();
+        assert_eq!(client.get_trader_concentration(), 5_000);
+    }
+
+    #[test]
+    fn test_ttl_policy() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert_eq!(client.get_ttl_policy(), (17_280, 518_400));
+        assert_eq!(
+            client.try_set_ttl_policy(&200, &100),
+            Err(Ok(Error::InvalidTtl))
+        );
+
+        client.set_ttl_policy(&5_000, &10_000);
+        assert_eq!(client.get_ttl_policy(), (5_000, 10_000));
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+
+        let ttl = env.as_contract(&client.address, || {
+            env.storage().persistent().get_ttl(&DataKey::Swap(0))
+        });
+        assert_eq!(ttl, 10_000);
+    }
+
+    #[test]
+    fn test_user_swap_at() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        record(&client, &other, &xlm, &usdc, 999, 1500);
+        record(&client, &user, &xlm, &usdc, 200, 2000);
+        record(&client, &user, &xlm, &usdc, 300, 3000);
+
+        assert_eq!(client.get_user_swap_at(&user, &0).unwrap().amount, 300);
+        assert_eq!(client.get_user_swap_at(&user, &1).unwrap().amount, 200);
+        assert_eq!(client.get_user_swap_at(&user, &2).unwrap().amount, 100);
+        assert_eq!(client.get_user_swap_at(&user, &3), None);
+    }
+
+    #[test]
+    fn test_new_trader_event() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let new_trader = Symbol::new(&env, "new_trader");
+
+        record(&client, &user, &xlm, &usdc, 100, 1000);
+        assert_eq!(count_events(&env, new_trader.clone()), 1);
+        let (_, _, payload) = env.events().all().last().unwrap();
+        assert_eq!(Address::try_from_val(&env, &payload).unwrap(), user);
+
+        record(&client, &user, &xlm, &usdc, 200, 2000);
+        assert_eq!(count_events(&env, new_trader), 1);
+    }
+
+    #[test]
+    fn test_assets_paginated() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let codes = ["XLM", "USDC", "EURC", "BTC", "ETH"];
+        for (i, pair) in codes.windows(2).enumerate() {
+            let from = String::from_str(&env, pair[0]);
+            let to = String::from_str(&env, pair[1]);
+            record(&client, &user, &from, &to, 100, 1000 + i as u64);
+        }
+        assert_eq!(client.get_asset_list().len(), 5);
+
+        let page = client.get_assets_paginated(&2, &2);
+        assert_eq!(
+            page,
+            vec![
+                &env,
+                String::from_str(&env, "EURC"),
+                String::from_str(&env, "BTC")
+            ]
+        );
+        assert_eq!(client.get_assets_paginated(&4, &10).len(), 1);
+        assert_eq!(client.get_assets_paginated(&5, &2).len(), 0);
+    }
+
+    #[test]
+    fn test_expire_stale_swaps() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_confirmation_threshold(&1);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let with_deadline = |amount, deadline_ts| SwapInput {
+            deadline_ts,
+            ..swap_input(&user, &xlm, &usdc, amount, 1000)
+        };
+
+        submit(&client, &with_deadline(100, 5000));
+        submit(&client, &with_deadline(200, 9000));
+        submit(&client, &with_deadline(300, 0));
+        submit(&client, &with_deadline(400, 5000));
+        client.add_confirmation(&3);
+
+        env.ledger().with_mut(|l| l.timestamp = 6000);
+        assert_eq!(client.expire_stale_swaps(&10), 1);
+        assert_eq!(client.get_swap(&0).unwrap().status, SwapStatus::Failed);
+        assert_eq!(client.get_swap(&1).unwrap().status, SwapStatus::Pending);
+        assert_eq!(client.get_swap(&2).unwrap().status, SwapStatus::Pending);
+        assert_eq!(client.get_swap(&3).unwrap().status, SwapStatus::Completed);
+        assert_eq!(count_events(&env, symbol_short!("expired")), 1);
+
+        // Expired swaps are not processed again
+        env.ledger().with_mut(|l| l.timestamp = 10_000);
+        assert_eq!(client.expire_stale_swaps(&10), 1);
+        assert_eq!(client.get_swap(&1).unwrap().status, SwapStatus::Failed);
+        assert_eq!(client.expire_stale_swaps(&10), 0);
+    }
+
+    #[test]
+    fn test_get_summary() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        assert_eq!(
+            client.get_summary(),
+            Summary {
+                total_swaps: 0,
+                last_timestamp: 0,
+                distinct_assets: 0,
+                paused: false,
+            }
+        );
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let btc = String::from_str(&env, "BTC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 2000));
+        submit(&client, &swap_input(&user, &usdc, &btc, 50, 1500));
+        client.pause();
+
+        assert_eq!(
+            client.get_summary(),
+            Summary {
+                total_swaps: 2,
+                last_timestamp: 2000,
+                distinct_assets: 3,
+                paused: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_user_volume_in_range() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let day = SECONDS_PER_DAY;
+
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, day / 2));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, day + 10));
+        submit(&client, &swap_input(&other, &xlm, &usdc, 1000, day + 20));
+        submit(&client, &swap_input(&user, &usdc, &xlm, 300, 2 * day + 10));
+        submit(&client, &swap_input(&user, &usdc, &xlm, 400, 2 * day + 20));
+
+        assert_eq!(
+            client.get_user_volume_in_range(&user, &day, &(3 * day - 1)),
+            900
+        );
+        assert_eq!(client.get_user_volume_in_range(&user, &0, &(day - 1)), 100);
+        assert_eq!(client.get_user_volume_in_range(&other, &0, &day), 0);
+    }
+
+    #[test]
+    fn test_pair_allowlist() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        // Any pair is accepted until enforcement is turned on
+        submit(&client, &swap_input(&user, &usdc, &xlm, 100, 1000));
+
+        client.set_pair_allowlist_enabled(&true);
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1100)),
+            Err(Ok(Error::PairNotApproved))
+        );
+
+        client.approve_pair(&xlm, &usdc);
+        assert!(client.is_pair_approved(&xlm, &usdc));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1200));
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &usdc, &xlm, 100, 1300)),
+            Err(Ok(Error::PairNotApproved))
+        );
+
+        client.revoke_pair(&xlm, &usdc);
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1400)),
+            Err(Ok(Error::PairNotApproved))
+        );
+        assert_eq!(client.get_swap_count(), 2);
+    }
+
+    #[test]
+    fn test_get_cumulative_series() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        for (amount, ts) in [(100, 1000), (200, 1100), (300, 1200)] {
+            submit(&client, &swap_input(&user, &xlm, &usdc, amount, ts));
+        }
+
+        assert_eq!(client.get_cumulative_series(&3), vec![&env, 100, 300, 600]);
+        assert_eq!(client.get_cumulative_series(&2), vec![&env, 300, 600]);
+        assert_eq!(client.get_cumulative_series(&10), vec![&env, 100, 300, 600]);
+    }
+
+    #[test]
+    fn test_records_stored_with_asset_ids() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &usdc, &xlm, 50, 1100));
+
+        let (first, second) = env.as_contract(&client.address, || {
+            let storage = env.storage().persistent();
+            let first: StoredSwap = storage.get(&DataKey::Swap(0)).unwrap();
+            let second: StoredSwap = storage.get(&DataKey::Swap(1)).unwrap();
+            (first, second)
+        });
+        assert_eq!((first.from_asset, first.to_asset), (0, 1));
+        assert_eq!((second.from_asset, second.to_asset), (1, 0));
+
+        let record = client.get_swap(&1).unwrap();
+        assert_eq!(
+            record,
+            SwapRecord {
+                user: user.clone(),
+                from_asset: usdc.clone(),
+                to_asset: xlm.clone(),
+                amount: 50,
+                to_amount: 50,
+                timestamp: 1100,
+                category: CATEGORY,
+                status: SwapStatus::Completed,
+                confirmations: 0,
+                swap_id: second.swap_id,
+                ledger_seq: env.ledger().sequence(),
+                parent_id: None,
+                app_id: APP,
+                deadline_ts: 0,
+                quote_value: 0,
+                fee: 0,
+                prev_hash: second.prev_hash,
+            }
+        );
+
+        // Ids survive the asset being merged out of the asset list
+        client.merge_asset(&usdc, &xlm);
+        assert_eq!(client.get_swap(&0).unwrap().to_asset, usdc);
+    }
+
+    #[test]
+    fn test_get_pair_list() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &usdc, &xlm, 100, 1100));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1200));
+
+        assert_eq!(
+            client.get_pair_list(),
+            vec![
+                &env,
+                (xlm.clone(), usdc.clone()),
+                (usdc.clone(), xlm.clone())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_user_since() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert_eq!(client.get_user_since(&user), None);
+
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &usdc, &xlm, 100, 5000));
+        assert_eq!(client.get_user_since(&user), Some(1000));
+    }
+
+    #[test]
+    fn test_get_swaps_in_bucket() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        for (amount, ts) in [(5, 1000), (50, 1100), (500, 1200)] {
+            submit(&client, &swap_input(&user, &xlm, &usdc, amount, ts));
+        }
+
+        let swaps = client.get_swaps_in_bucket(&1, &10);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps.get_unchecked(0).amount, 50);
+        assert!(client.get_swaps_in_bucket(&3, &10).is_empty());
+
+        let counts = client.get_bucket_counts();
+        assert_eq!(counts.len(), AMOUNT_BUCKETS);
+        assert_eq!(counts.slice(0..4), vec![&env, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_correct_swap() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let btc = String::from_str(&env, "BTC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+        assert_eq!(client.get_pair_volume(&xlm, &usdc), 300);
+
+        let mut corrected = client.get_swap(&0).unwrap();
+        corrected.from_asset = btc.clone();
+        corrected.amount = 40;
+        client.correct_swap(&0, &corrected);
+
+        assert_eq!(client.get_swap(&0), Some(corrected));
+        assert_eq!(client.get_swap_count(), 2);
+        assert_eq!(client.get_total_volume(), 240);
+        assert_eq!(client.get_pair_volume(&xlm, &usdc), 200);
+        assert_eq!(client.get_pair_volume(&btc, &usdc), 40);
+        assert_eq!(client.get_user_volume(&user), 240);
+        assert_eq!(client.get_user_swap_count(&user), 2);
+        assert_eq!(client.get_user_asset_volume(&user, &xlm), 200);
+        assert_eq!(client.get_user_asset_volume(&user, &btc), 40);
+        assert_eq!(client.get_asset_flow_counts(&xlm), (1, 0));
+        assert_eq!(client.get_asset_flow_counts(&btc), (1, 0));
+        assert_eq!(client.get_asset_flow_counts(&usdc), (0, 2));
+        assert_eq!(client.get_user_swap_indices(&user), vec![&env, 0, 1]);
+        assert_eq!(count_events(&env, symbol_short!("corrected")), 1);
+    }
+
+    #[test]
+    fn test_correct_swap_missing() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        let record = client.get_swap(&0).unwrap();
+
+        assert_eq!(
+            client.try_correct_swap(&5, &record),
+            Err(Ok(Error::SwapNotFound))
+        );
+    }
+
+    #[test]
+    fn test_correct_swap_validates_record() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+
+        let mut corrected = client.get_swap(&1).unwrap();
+        corrected.amount = MAX_SWAP_AMOUNT + 1;
+        assert_eq!(
+            client.try_correct_swap(&1, &corrected),
+            Err(Ok(Error::AmountTooLarge))
+        );
+        client.set_asset_min(&xlm, &50);
+        corrected.amount = 40;
+        assert_eq!(
+            client.try_correct_swap(&1, &corrected),
+            Err(Ok(Error::AmountTooSmall))
+        );
+        client.set_asset_min(&xlm, &0);
+        client.block_address(&user);
+        assert_eq!(
+            client.try_correct_swap(&1, &corrected),
+            Err(Ok(Error::AddressBlocked))
+        );
+        client.unblock_address(&user);
+        assert_eq!(client.get_swap(&1).unwrap().amount, 200);
+
+        // The stored cumulative totals stay stale until a rebuild
+        client.correct_swap(&1, &corrected);
+        assert_eq!(client.volume_between(&0, &1), 200);
+        client.rebuild_aggregates();
+        assert_eq!(client.volume_between(&0, &1), 40);
+        submit(&client, &swap_input(&user, &xlm, &usdc, 10, 1200));
+        assert_eq!(client.volume_between(&0, &2), 50);
+        assert_eq!(client.get_total_volume(), 150);
+    }
+
+    #[test]
+    fn test_freeze_blocks_reads_and_writes() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+
+        client.freeze();
+        assert!(client.is_frozen());
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1100)),
+            Err(Ok(Error::Frozen))
+        );
+        // Calls without a contract error type surface the raw code
+        let frozen = soroban_sdk::Error::from_contract_error(Error::Frozen as u32);
+        assert_eq!(client.try_get_recent_swaps(&10), Err(Ok(frozen)));
+        assert_eq!(client.try_get_swap_count(), Err(Ok(frozen)));
+        assert_eq!(client.try_to_stroops(&5, &7), Err(Ok(Error::Frozen)));
+
+        client.unfreeze();
+        assert!(!client.is_frozen());
+        assert_eq!(client.get_recent_swaps(&10).len(), 1);
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1100));
+    }
+
+    #[test]
+    fn test_get_recent_swaps_with_age() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1800));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 300, 5000));
+
+        env.ledger().with_mut(|l| l.timestamp = 2000);
+        let swaps = client.get_recent_swaps_with_age(&3);
+        let expected = [(300, 0), (200, 200), (100, 1000)];
+        assert_eq!(swaps.len(), 3);
+        for (position, (record, age)) in swaps.iter().enumerate() {
+            assert_eq!((record.amount, age), expected[position]);
+        }
+    }
+
+    #[test]
+    fn test_get_stale_pending() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_confirmation_threshold(&1);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+        client.add_confirmation(&1);
+
+        env.ledger().with_mut(|l| l.timestamp = 5000);
+        submit(&client, &swap_input(&user, &xlm, &usdc, 300, 5000));
+
+        let stale = client.get_stale_pending(&3600, &10);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale.get_unchecked(0).amount, 100);
+        assert_eq!(client.get_stale_pending(&0, &10).len(), 1);
+        assert_eq!(client.get_stale_pending(&5000, &10).len(), 0);
+    }
+
+    #[test]
+    fn test_get_pair_stats() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 400,
+                ..swap_input(&user, &xlm, &usdc, 200, 1100)
+            },
+        );
+        submit(&client, &swap_input(&user, &usdc, &xlm, 50, 1200));
+
+        assert_eq!(
+            client.get_pair_stats(&xlm, &usdc),
+            PairStats {
+                from_asset: xlm.clone(),
+                to_asset: usdc.clone(),
+                volume: 300,
+                count: 2,
+                last_rate: 2 * DEFAULT_RATE_SCALE,
+            }
+        );
+
+        let btc = String::from_str(&env, "BTC");
+        assert_eq!(
+            client.get_pair_stats(&btc, &usdc),
+            PairStats {
+                from_asset: btc.clone(),
+                to_asset: usdc.clone(),
+                volume: 0,
+                count: 0,
+                last_rate: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_swap_event_shard() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert_eq!(client.get_event_shards(), 1);
+        assert_eq!(
+            client.try_set_event_shards(&0),
+            Err(Ok(Error::InvalidShardCount))
+        );
+        client.set_event_shards(&4);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let mut shards = vec![&env];
+        for i in 0..6u64 {
+            submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000 + i));
+            let (topics, _) = last_swap_event(&env);
+            assert_eq!(topics.len(), 3);
+            shards.push_back(u32::try_from_val(&env, &topics.get(2).unwrap()).unwrap());
+        }
+        assert_eq!(shards, vec![&env, 0, 1, 2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn test_volume_between() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        for (amount, ts) in [(100, 1000), (200, 1100), (300, 1200), (400, 1300)] {
+            submit(&client, &swap_input(&user, &xlm, &usdc, amount, ts));
+        }
+
+        // The start index is exclusive and the end index inclusive
+        assert_eq!(client.volume_between(&1, &3), 700);
+        assert_eq!(client.volume_between(&0, &3), 900);
+        assert_eq!(client.volume_between(&2, &2), 0);
+        assert_eq!(
+            client.try_volume_between(&3, &1),
+            Err(Ok(Error::InvalidRange))
+        );
+        assert_eq!(
+            client.try_volume_between(&1, &4),
+            Err(Ok(Error::InvalidRange))
+        );
+    }
+
+    #[test]
+    fn test_volume_between_across_gaps() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+
+        // Import at 3, skipping index 2
+        let mut imported = client.get_swap(&1).unwrap();
+        imported.amount = 400;
+        imported.swap_id = BytesN::from_array(&env, &[3; 32]);
+        client.set_import_mode(&true);
+        client.import_swap(&3, &imported);
+        submit(&client, &swap_input(&user, &xlm, &usdc, 50, 1200));
+        assert_eq!(client.volume_between(&0, &4), 650);
+        assert_eq!(client.volume_between(&3, &4), 50);
+        assert_eq!(
+            client.try_volume_between(&2, &4),
+            Err(Ok(Error::SwapNotFound))
+        );
+
+        // Raise the count past empty indices
+        client.set_maintenance_mode(&true);
+        client.set_swap_count(&8);
+        submit(&client, &swap_input(&user, &xlm, &usdc, 25, 1300));
+        assert_eq!(client.volume_between(&4, &8), 25);
+        assert_eq!(client.volume_between(&0, &8), 675);
+    }
+
+    #[test]
+    fn test_swap_hook_receives_record() {
+        let env = Env::default();
+        let client = setup(&env);
+        let hook_id = env.register_contract(None, MockHook);
+        let hook = MockHookClient::new(&env, &hook_id);
+        client.set_hook_contract(&Some(hook_id.clone()));
+        assert_eq!(client.get_hook_contract(), Some(hook_id));
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        assert_eq!(hook.last_swap(), client.get_swap(&0));
+
+        client.set_hook_contract(&None);
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+        assert_eq!(hook.last_swap(), client.get_swap(&0));
+    }
+
+    #[test]
+    fn test_failing_hook_does_not_abort_swap() {
+        let env = Env::default();
+        let client = setup(&env);
+        let hook_id = env.register_contract(None, MockHook);
+        MockHookClient::new(&env, &hook_id).set_failing();
+        client.set_hook_contract(&Some(hook_id));
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+
+        assert_eq!(client.get_swap_count(), 1);
+        assert_eq!(count_events(&env, Symbol::new(&env, "hook_failed")), 1);
+    }
+
+    #[test]
+    fn test_schema_version_migration() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert_eq!(client.schema_version(), 1);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &usdc, &xlm, 200, 1100));
+
+        // Put the first record back into the unversioned layout and
+        // corrupt the second
+        let second: Val = env.as_contract(&client.address, || {
+            let storage = env.storage().persistent();
+            let legacy = LegacySwap {
+                user: user.clone(),
+                from_asset: xlm.clone(),
+                to_asset: usdc.clone(),
+                amount: 100,
+                timestamp: 1000,
+            };
+            storage.set(&DataKey::Swap(0), &legacy);
+            let second: Val = storage.get(&DataKey::Swap(1)).unwrap();
+            storage.set(&DataKey::Swap(1), &symbol_short!("bad"));
+            second
+        });
+        let upgraded = client.get_swap(&0).unwrap();
+        assert_eq!(upgraded.amount, 100);
+
+        assert_eq!(client.migrate_schema(&0, &1), 1);
+        assert_eq!(client.schema_version(), 1);
+        assert_eq!(client.migrate_schema(&1, &10), 0);
+        assert_eq!(client.schema_version(), 1);
+
+        env.as_contract(&client.address, || {
+            env.storage().persistent().set(&DataKey::Swap(1), &second);
+        });
+        assert_eq!(client.migrate_schema(&0, &10), 0);
+        assert_eq!(client.schema_version(), SCHEMA_VERSION);
+        assert_eq!(client.get_swap(&0), Some(upgraded));
+        let stored = env.as_contract(&client.address, || {
+            let raw: Val = env.storage().persistent().get(&DataKey::Swap(0)).unwrap();
+            StoredSwap::try_from_val(&env, &raw).is_ok()
+        });
+        assert!(stored);
+    }
+
+    #[test]
+    fn test_read_legacy_records() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_max_records(&2);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        env.as_contract(&client.address, || {
+            let storage = env.storage().persistent();
+            for index in 0..2u64 {
+                let legacy = LegacySwap {
+                    user: user.clone(),
+                    from_asset: xlm.clone(),
+                    to_asset: usdc.clone(),
+                    amount: 100 * (index as i128 + 1),
+                    timestamp: 1000 + index,
+                };
+                storage.set(&DataKey::Swap(index), &legacy);
+            }
+            storage.set(&DataKey::SwapCount, &2u64);
+        });
+
+        let record = client.get_swap(&1).unwrap();
+        assert_eq!((record.amount, record.timestamp), (200, 1001));
+        assert_eq!(
+            (record.from_asset, record.to_asset),
+            (xlm.clone(), usdc.clone())
+        );
+        assert_eq!(record.status, SwapStatus::Completed);
+        assert_eq!(record.category, LEGACY_TAG);
+        assert_eq!(client.get_recent_swaps(&5).len(), 2);
+
+        // Recording rolls the oldest legacy record off the capped history
+        submit(&client, &swap_input(&user, &xlm, &usdc, 300, 2000));
+        assert_eq!(client.get_swap(&0), None);
+        assert_eq!(client.get_swap(&1).unwrap().amount, 200);
+    }
+
+    #[test]
+    fn test_record_cap_drops_oldest() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_max_records(&2);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        for i in 0..5u64 {
+            submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000 + i));
+        }
+
+        assert_eq!(client.get_dropped_count(), 3);
+        assert_eq!(client.get_swap_count(), 5);
+        assert_eq!(client.get_swap(&2), None);
+        assert!(client.get_swap(&3).is_some());
+        assert_eq!(client.get_user_swap_indices(&user), vec![&env, 3, 4]);
+        assert_eq!(client.get_recent_swaps(&10).len(), 2);
+        // Aggregates keep the dropped records' contribution, except the
+        // status counts, which track live records
+        assert_eq!(client.get_total_volume(), 500);
+        assert_eq!(client.get_status_count(&SwapStatus::Completed), 2);
+    }
+
+    #[test]
+    fn test_record_cap_keeps_trader_history() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_max_records(&1);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let new_trader = Symbol::new(&env, "new_trader");
+
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&other, &xlm, &usdc, 100, 1100));
+        assert!(client.get_user_swap_indices(&user).is_empty());
+        assert_eq!(client.get_user_since(&user), Some(1000));
+        assert_eq!(count_events(&env, new_trader.clone()), 2);
+
+        // Returning after the roll-off is not a new trader
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1200));
+        assert_eq!(client.get_user_since(&user), Some(1000));
+        assert_eq!(count_events(&env, new_trader), 2);
+    }
+
+    #[test]
+    fn test_private_user_masked_in_reads() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&other, &xlm, &usdc, 200, 1100));
+
+        client.set_private(&user, &true);
+        assert!(client.is_private(&user));
+
+        let placeholder = Address::from_string(&String::from_str(&env, PRIVATE_PLACEHOLDER));
+        let recent = client.get_recent_swaps(&10);
+        assert_eq!(recent.get_unchecked(0).user, other);
+        assert_eq!(recent.get_unchecked(1).user, placeholder);
+        assert_eq!(client.get_swap(&0).unwrap().user, placeholder);
+        assert_eq!(client.get_user_swap_count(&user), 1);
+        assert_eq!(client.get_user_swap_indices(&user), vec![&env, 0]);
+
+        client.set_private(&user, &false);
+        assert_eq!(client.get_swap(&0).unwrap().user, user);
+    }
+
+    #[test]
+    fn test_get_swaps_time_sorted() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        for (amount, ts) in [(100, 3000), (200, 1000), (300, 2000), (400, 500)] {
+            submit(&client, &swap_input(&user, &xlm, &usdc, amount, ts));
+        }
+
+        let sorted = client.get_swaps_time_sorted(&3);
+        assert_eq!(sorted.len(), 3);
+        let stamps = [3000, 2000, 1000];
+        for (position, record) in sorted.iter().enumerate() {
+            assert_eq!(record.timestamp, stamps[position]);
+        }
+        assert!(client.get_swaps_time_sorted(&0).is_empty());
+    }
+
+    #[test]
+    fn test_display_amount() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let usdc = String::from_str(&env, "USDC");
+        let xlm = String::from_str(&env, "XLM");
+        client.set_asset_decimals(&usdc, &6);
+        assert_eq!(
+            client.try_set_asset_decimals(&usdc, &19),
+            Err(Ok(Error::InvalidDecimals))
+        );
+
+        assert_eq!(client.display_amount(&usdc, &1_500_000), (1, 500_000));
+        assert_eq!(client.display_amount(&xlm, &1_500_000), (0, 1_500_000));
+        assert_eq!(client.display_amount(&xlm, &-25_000_000), (-2, -5_000_000));
+    }
+
+    #[test]
+    fn test_set_statuses_batch() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_confirmation_threshold(&1);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        for i in 0..4u64 {
+            submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000 + i));
+        }
+        assert_eq!(client.get_status_count(&SwapStatus::Pending), 4);
+
+        let updated = client.set_statuses_batch(&vec![&env, 0, 2, 3, 9], &SwapStatus::Completed);
+        assert_eq!(updated, 3);
+        assert_eq!(client.get_status_count(&SwapStatus::Pending), 1);
+        assert_eq!(client.get_status_count(&SwapStatus::Completed), 3);
+        assert_eq!(client.get_swap(&2).unwrap().status, SwapStatus::Completed);
+        assert_eq!(client.get_user_reputation(&user), 100);
+        assert_eq!(count_events(&env, Symbol::new(&env, "status_changed")), 3);
+
+        // Already completed records are left alone
+        let updated = client.set_statuses_batch(&vec![&env, 0, 1], &SwapStatus::Completed);
+        assert_eq!(updated, 1);
+        assert_eq!(client.get_status_count(&SwapStatus::Pending), 0);
+        assert_eq!(client.get_stale_pending(&0, &10).len(), 0);
+    }
+
+    #[test]
+    fn test_get_busiest_day() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert_eq!(client.get_busiest_day(), None);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let day = SECONDS_PER_DAY;
+        submit(&client, &swap_input(&user, &xlm, &usdc, 500, 3 * day));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 300, 4 * day));
+        assert_eq!(client.get_busiest_day(), Some((3, 500)));
+
+        submit(&client, &swap_input(&user, &xlm, &usdc, 300, 4 * day + 60));
+        assert_eq!(client.get_busiest_day(), Some((4, 600)));
+    }
+
+    #[test]
+    fn test_configure_assets() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let eth = String::from_str(&env, "ETH");
+        client.configure_assets(&vec![
+            &env,
+            (xlm.clone(), 7, 1_200_000),
+            (usdc.clone(), 6, 10_000_000),
+            (eth.clone(), 18, 30_000_000_000),
+        ]);
+
+        assert_eq!(client.get_asset_decimals(&xlm), 7);
+        assert_eq!(client.get_asset_decimals(&usdc), 6);
+        assert_eq!(client.get_asset_decimals(&eth), 18);
+        assert_eq!(client.get_asset_price(&xlm), Some(1_200_000));
+        assert_eq!(client.get_asset_price(&usdc), Some(10_000_000));
+        assert_eq!(client.get_asset_price(&eth), Some(30_000_000_000));
+
+        assert_eq!(
+            client.try_configure_assets(&vec![&env, (xlm.clone(), 19, 0)]),
+            Err(Ok(Error::InvalidDecimals))
+        );
+        assert_eq!(
+            client.try_configure_assets(&vec![&env, (usdc.clone(), 6, 5), (xlm.clone(), 7, -1)]),
+            Err(Ok(Error::InvalidPrice))
+        );
+        assert_eq!(client.get_asset_price(&usdc), Some(10_000_000));
+    }
+
+    #[test]
+    fn test_get_amount_percentile() {
+        let env = Env::default();
+        let client = setup(&env);
+        assert_eq!(client.get_amount_percentile(&50), 0);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        // 12 swaps in [10, 100), 7 in [1000, 10000) and 1 in [10^6, 10^7)
+        let mut ts = 1000;
+        for (amount, count) in [(50, 12), (5_000, 7), (5_000_000, 1)] {
+            for _ in 0..count {
+                submit(&client, &swap_input(&user, &xlm, &usdc, amount, ts));
+                ts += 1;
+            }
+        }
+
+        assert_eq!(client.get_amount_percentile(&0), bucket_midpoint(1));
+        assert_eq!(client.get_amount_percentile(&50), bucket_midpoint(1));
+        assert_eq!(client.get_amount_percentile(&95), bucket_midpoint(3));
+        assert_eq!(client.get_amount_percentile(&96), bucket_midpoint(6));
+        assert_eq!(client.get_amount_percentile(&250), bucket_midpoint(6));
+        assert_eq!(client.get_approx_median_amount(), bucket_midpoint(1));
+    }
+
+    #[test]
+    fn test_pair_exists() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert!(!client.pair_exists(&xlm, &usdc));
+
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        assert!(client.pair_exists(&xlm, &usdc));
+        assert!(!client.pair_exists(&usdc, &xlm));
+    }
+
+    #[test]
+    fn test_swap_event_carries_rate() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        type SwapEvent = (
+            Address,
+            String,
+            String,
+            i128,
+            u64,
+            u32,
+            TickDirection,
+            i128,
+            bool,
+        );
+
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 250,
+                ..swap_input(&user, &xlm, &usdc, 1000, 1000)
+            },
+        );
+        let (_, data) = last_swap_event(&env);
+        let event = SwapEvent::try_from_val(&env, &data).unwrap();
+        assert_eq!((event.7, event.8), (DEFAULT_RATE_SCALE / 4, false));
+
+        submit(
+            &client,
+            &SwapInput {
+                to_amount: 0,
+                ..swap_input(&user, &xlm, &usdc, 1000, 1100)
+            },
+        );
+        let (_, data) = last_swap_event(&env);
+        let event = SwapEvent::try_from_val(&env, &data).unwrap();
+        assert_eq!((event.7, event.8), (0, true));
+    }
+
+    #[test]
+    fn test_previous_and_next_swap() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        for (amount, ts) in [(100, 1000), (200, 1100), (300, 1200)] {
+            submit(&client, &swap_input(&user, &xlm, &usdc, amount, ts));
+        }
+        env.as_contract(&client.address, || {
+            env.storage().persistent().remove(&DataKey::Swap(1));
+        });
+
+        assert_eq!(client.get_previous_swap(&2).unwrap().amount, 100);
+        assert_eq!(client.get_next_swap(&0).unwrap().amount, 300);
+        assert_eq!(client.get_previous_swap(&0), None);
+        assert_eq!(client.get_next_swap(&2), None);
+        assert_eq!(client.get_previous_swap(&10).unwrap().amount, 300);
+    }
+
+    #[test]
+    fn test_quote_value() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        // One XLM is worth 0.12 in the quote currency
+        client.set_asset_price(&xlm, &1_200_000);
+
+        submit(&client, &swap_input(&user, &xlm, &usdc, 50_000_000, 1000));
+        submit(&client, &swap_input(&user, &usdc, &xlm, 1_000, 1100));
+
+        assert_eq!(client.get_swap(&0).unwrap().quote_value, 6_000_000);
+        assert_eq!(client.get_swap(&1).unwrap().quote_value, 0);
+        assert_eq!(client.get_total_quote_value(), 6_000_000);
+    }
+
+    #[test]
+    fn test_get_recent_traders() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let a = Address::generate(&env);
+        let b = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&a, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&b, &xlm, &usdc, 200, 1100));
+        submit(&client, &swap_input(&a, &xlm, &usdc, 300, 1200));
+
+        assert_eq!(client.get_recent_traders(&3), vec![&env, a.clone(), b]);
+        assert_eq!(client.get_recent_traders(&1), vec![&env, a]);
+    }
+
+    #[test]
+    fn test_require_monotonic_ts() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert!(!client.is_monotonic_ts_required());
+        client.set_require_monotonic_ts(&true);
+        assert!(client.is_monotonic_ts_required());
+
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1000)),
+            Err(Ok(Error::TimestampCollision))
+        );
+
+        // Other users and later timestamps are unaffected
+        submit(&client, &swap_input(&other, &xlm, &usdc, 200, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1001));
+        assert_eq!(client.get_swap_count(), 3);
+
+        // The check keeps working while aggregation is disabled
+        client.set_aggregation_enabled(&false);
+        submit(&client, &swap_input(&user, &xlm, &usdc, 300, 1002));
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, 400, 1002)),
+            Err(Ok(Error::TimestampCollision))
+        );
+    }
+
+    #[test]
+    fn test_get_turnover() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 300, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+        assert_eq!(client.get_turnover(&xlm), 0);
+
+        client.set_asset_supply(&xlm, &2_000);
+        assert_eq!(client.get_asset_supply(&xlm), 2_000);
+        // 500 traded against a supply of 2000 is a quarter of the scale
+        assert_eq!(client.get_turnover(&xlm), client.get_rate_scale() / 4);
+        assert_eq!(
+            client.try_set_asset_supply(&xlm, &-1),
+            Err(Ok(Error::InvalidSupply))
+        );
+
+        // A maximal volume times a fine scale would overflow
+        record(&client, &user, &usdc, &xlm, MAX_SWAP_AMOUNT, 1200);
+        client.set_rate_scale(&1_000_000_000);
+        client.set_asset_supply(&usdc, &10_i128.pow(24));
+        assert_eq!(client.get_turnover(&usdc), 1_000_000 * 1_000_000_000);
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+        submit(&client, &swap_input(&user, &usdc, &xlm, 300, 1200));
+
+        assert_eq!(
+            client.get_swap(&0).unwrap().prev_hash,
+            BytesN::from_array(&env, &[0; 32])
+        );
+        assert!(client.verify_chain(&2));
+        assert!(!client.verify_chain(&3));
+
+        // The newest record is checked against the chain head, swap id
+        // included
+        env.as_contract(&client.address, || {
+            let storage = env.storage().persistent();
+            let mut stored: StoredSwap = storage.get(&DataKey::Swap(2)).unwrap();
+            stored.swap_id = BytesN::from_array(&env, &[9; 32]);
+            storage.set(&DataKey::Swap(2), &stored);
+        });
+        assert!(!client.verify_chain(&2));
+        assert!(client.verify_chain(&1));
+
+        // Rewriting the middle record breaks the link from the next one
+        env.as_contract(&client.address, || {
+            let storage = env.storage().persistent();
+            let mut stored: StoredSwap = storage.get(&DataKey::Swap(1)).unwrap();
+            stored.amount = 250;
+            storage.set(&DataKey::Swap(1), &stored);
+        });
+        assert!(client.verify_chain(&1));
+        assert!(!client.verify_chain(&2));
+    }
+
+    #[test]
+    fn test_get_active_day_count() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert_eq!(client.get_active_day_count(), 0);
+
+        record(&client, &user, &xlm, &usdc, 100, 3 * 86_400 + 10);
+        record(&client, &user, &xlm, &usdc, 100, 3 * 86_400 + 500);
+        record(&client, &user, &xlm, &usdc, 100, 4 * 86_400 + 10);
+        assert_eq!(client.get_active_day_count(), 2);
+
+        client.rebuild_aggregates();
+        assert_eq!(client.get_active_day_count(), 2);
+    }
+
+    #[test]
+    fn test_get_intraday_volume() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let day = 3 * 86_400;
+        record(&client, &user, &xlm, &usdc, 100, day + 10);
+        record(&client, &user, &xlm, &usdc, 150, day + 3_599);
+        record(&client, &user, &xlm, &usdc, 70, day + 13 * 3_600 + 5);
+        // Same hour of the next day
+        record(&client, &user, &xlm, &usdc, 900, day + 86_400 + 10);
+
+        let volumes = client.get_intraday_volume(&3);
+        assert_eq!(volumes.len(), 24);
+        assert_eq!(volumes.get_unchecked(0), 250);
+        assert_eq!(volumes.get_unchecked(13), 70);
+        assert_eq!(volumes.iter().sum::<i128>(), 320);
+
+        let far = client.get_intraday_volume(&u64::MAX);
+        assert_eq!(far.len(), 24);
+        assert!(far.iter().all(|volume| volume == 0));
+    }
+
+    #[test]
+    fn test_set_swap_count() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 300, 1200));
+
+        client.set_maintenance_mode(&true);
+        assert!(client.is_maintenance_mode());
+        assert_eq!(client.try_set_swap_count(&1), Err(Ok(Error::IndexTaken)));
+
+        // Surgery removed the newest record, leaving the count one too high
+        env.as_contract(&client.address, || {
+            env.storage().persistent().remove(&DataKey::Swap(2));
+        });
+        client.set_swap_count(&2);
+        assert_eq!(count_events(&env, Symbol::new(&env, "count_adjusted")), 1);
+        assert_eq!(client.get_swap_count(), 2);
+        assert_eq!(client.get_recent_swaps(&5).len(), 2);
+        assert!(client.health_check());
+
+        // The next swap links onto the surviving records
+        submit(&client, &swap_input(&user, &xlm, &usdc, 400, 1300));
+        assert!(client.verify_chain(&2));
+    }
+
+    #[test]
+    fn test_set_swap_count_respects_dropped_records() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_max_records(&1);
+        client.set_maintenance_mode(&true);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        submit(&client, &swap_input(&user, &xlm, &usdc, 100, 1000));
+        submit(&client, &swap_input(&user, &xlm, &usdc, 200, 1100));
+
+        assert_eq!(client.get_dropped_count(), 1);
+        assert_eq!(client.try_set_swap_count(&0), Err(Ok(Error::InvalidRange)));
+        assert_eq!(client.get_swap_count(), 2);
+    }
+
+    #[test]
+    fn test_set_swap_count_requires_maintenance_mode() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        assert!(!client.is_maintenance_mode());
+        assert_eq!(
+            client.try_set_swap_count(&5),
+            Err(Ok(Error::MaintenanceDisabled))
+        );
+        assert_eq!(client.get_swap_count(), 0);
+    }
+
+    #[test]
+    fn test_correct_swap_uses_stored_fee() {
+        let env = Env::default();
+        let client = setup(&env);
+        client.set_fee_bps(&100);
+
+        let user = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        record(&client, &user, &xlm, &usdc, 10_000, 1000);
+        assert_eq!(client.get_swap(&0).unwrap().fee, 100);
+
+        // A later rate change affects neither the reversal nor a rebuild
+        client.set_fee_bps(&500);
+        let mut corrected = client.get_swap(&0).unwrap();
+        corrected.amount = 20_000;
+        corrected.fee = 200;
+        client.correct_swap(&0, &corrected);
+        assert_eq!(client.get_asset_fees(&xlm), 200);
+
+        client.rebuild_aggregates();
+        assert_eq!(client.get_asset_fees(&xlm), 200);
+    }
+
+    #[test]
+    fn test_huge_swap_does_not_block_recording() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        assert_eq!(
+            try_submit(&client, &swap_input(&user, &xlm, &usdc, i128::MAX, 1000)),
+            Err(Ok(Error::AmountTooLarge))
+        );
+        submit(
+            &client,
+            &swap_input(&user, &xlm, &usdc, MAX_SWAP_AMOUNT, 1000),
+        );
+        submit(&client, &swap_input(&other, &xlm, &usdc, 1, 1100));
+
+        // A total at the limit fails the swap cleanly and rolls it back
+        env.as_contract(&client.address, || {
+            env.storage()
+                .persistent()
+                .set(&DataKey::TotalVolume, &i128::MAX);
+        });
+        assert_eq!(
+            try_submit(&client, &swap_input(&other, &xlm, &usdc, 1, 1200)),
+            Err(Ok(Error::Overflow))
+        );
+        assert_eq!(client.get_swap_count(), 2);
+        assert_eq!(client.get_user_swap_count(&other), 1);
     }
 }
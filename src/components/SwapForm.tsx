@@ -151,6 +151,7 @@ export const SwapForm: React.FC<SwapFormProps> = ({
         sellAsset.code,
         buyAsset.code,
         sellAmount,
+        (parseFloat(sellAmount) * parseFloat(bestPrice)).toFixed(7),
         signTransaction
       ).catch((err) => console.warn('Failed to record swap:', err));
 
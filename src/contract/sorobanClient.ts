@@ -1,5 +1,5 @@
 import * as StellarSdk from '@stellar/stellar-sdk';
-import { SOROBAN_RPC_URL, NETWORK_PASSPHRASE, SWAP_TRACKER_CONTRACT_ID } from '../utils/constants';
+import { SOROBAN_RPC_URL, NETWORK_PASSPHRASE, SWAP_TRACKER_CONTRACT_ID, SWAP_CATEGORY, SWAP_APP_ID } from '../utils/constants';
 
 const sorobanServer = new StellarSdk.SorobanRpc.Server(SOROBAN_RPC_URL);
 
@@ -29,6 +29,7 @@ export interface SwapRecordData {
  * @param fromAsset - Source asset code
  * @param toAsset - Destination asset code
  * @param amount - Swap amount
+ * @param toAmount - Amount of the destination asset received
  * @param signTx - Callback to sign via wallet (delegates to wallet extension)
  * @returns Transaction hash on success
  */
@@ -37,6 +38,7 @@ export async function recordSwap(
   fromAsset: string,
   toAsset: string,
   amount: string,
+  toAmount: string,
   signTx: (xdr: string) => Promise<string>
 ): Promise<string> {
   if ((SWAP_TRACKER_CONTRACT_ID as string) === 'PLACEHOLDER_CONTRACT_ID') {
@@ -50,6 +52,7 @@ export async function recordSwap(
 
     const timestamp = Math.floor(Date.now() / 1000);
     const amountInStroops = Math.floor(parseFloat(amount) * 10_000_000);
+    const toAmountInStroops = Math.floor(parseFloat(toAmount) * 10_000_000);
 
     const tx = new StellarSdk.TransactionBuilder(account, {
       fee: StellarSdk.BASE_FEE,
@@ -62,7 +65,14 @@ export async function recordSwap(
           StellarSdk.nativeToScVal(fromAsset, { type: 'string' }),
           StellarSdk.nativeToScVal(toAsset, { type: 'string' }),
           StellarSdk.nativeToScVal(amountInStroops, { type: 'i128' }),
-          StellarSdk.nativeToScVal(timestamp, { type: 'u64' })
+          StellarSdk.nativeToScVal(toAmountInStroops, { type: 'i128' }),
+          StellarSdk.nativeToScVal(timestamp, { type: 'u64' }),
+          StellarSdk.nativeToScVal(SWAP_CATEGORY, { type: 'symbol' }),
+          // Wallet swaps are never partial fills
+          StellarSdk.xdr.ScVal.scvVoid(),
+          StellarSdk.nativeToScVal(SWAP_APP_ID, { type: 'symbol' }),
+          // Wallet swaps settle immediately, so no expiry deadline
+          StellarSdk.nativeToScVal(0, { type: 'u64' })
         )
       )
       .setTimeout(180)
@@ -223,7 +233,8 @@ export async function pollSwapEvents(
         {
           type: 'contract',
           contractIds: [SWAP_TRACKER_CONTRACT_ID],
-          topics: [['AAAADwAAAARzd2Fw']], // symbol "swap" as base64 ScVal
+          // symbol "swap" as base64 ScVal, followed by any event version and shard
+          topics: [['AAAADwAAAARzd2Fw', '*', '*']],
         },
       ],
       limit: 20,
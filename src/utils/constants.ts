@@ -17,6 +17,14 @@ export const FRIENDBOT_URL = 'https://friendbot.stellar.org';
 // Update this after contract deployment
 export const SWAP_TRACKER_CONTRACT_ID = 'CBEWIQV4KSH4KXA5V7B5ELMQM7WY7JTCTHB5DEPEFVJRLL62FGMJULOY';
 
+// Category attached to swaps recorded by this dApp
+// Must be allowed by the contract admin via set_category_allowed
+export const SWAP_CATEGORY = 'spot';
+
+// Source application id attached to swaps recorded by this dApp
+// Must be allowed by the contract admin via set_app_allowed
+export const SWAP_APP_ID = 'web';
+
 // Supported assets for token swaps
 export interface AssetConfig {
   code: string;